@@ -4,6 +4,8 @@ use std::f32::NAN;
 
 use bevy::prelude::*;
 
+use crate::config::config;
+
 /// flag of a blob entity
 #[derive(Component)]
 pub struct Blob;
@@ -24,7 +26,17 @@ pub struct BlobInfo {
     /// cumulated moving distance,
     /// base on `mass_center`
     pub move_distance: [f32;2],
-    pub crowding_distance: f32
+    pub crowding_distance: f32,
+    /// `mass_center` sampled `NoveltyConfig::trajectory_samples` times in current iteration
+    pub trajectory: Vec<[f32;2]>,
+    /// joint angles of all frames in current iteration
+    pub joint_stats: JointStats,
+    /// energy left, motors stop at zero, see `EnergyConfig`
+    pub energy: f32,
+    /// cumulated energy spent in current iteration
+    pub energy_used: f32,
+    /// food eaten in current iteration
+    pub food_collected: usize,
 }
 
 impl Default for BlobInfo {
@@ -37,7 +49,12 @@ impl Default for BlobInfo {
             mass_center: [0.0, 0.0],
             velocity: [0.0,0.0],
             move_distance: [0.0,0.0],
-            crowding_distance: 0.0
+            crowding_distance: 0.0,
+            trajectory: Vec::new(),
+            joint_stats: JointStats::default(),
+            energy: config().energy.initial,
+            energy_used: 0.0,
+            food_collected: 0,
         }
     }
 }
@@ -62,6 +79,46 @@ impl BlobInfo {
         self.ybound[0] = self.ybound[0].min(small.y);
         self.ybound[1] = self.ybound[1].max(large.y);
     }
+
+    /// energy never drops below zero, but all of `amount` counts as used
+    pub fn spend(&mut self, amount: f32) {
+        self.energy = (self.energy - amount).max(0.0);
+        self.energy_used += amount;
+    }
+
+    pub fn eat(&mut self, energy: f32) {
+        self.food_collected += 1;
+        self.energy += energy;
+    }
+}
+
+/// cumulated joint angles of a blob, for behaviour descriptors
+#[derive(Clone, Debug, Default)]
+pub struct JointStats {
+    sum_pos: f32,
+    sum_pos_sq: f32,
+    sum_abs_velocity: f32,
+    count: usize,
+}
+
+impl JointStats {
+    pub fn push(&mut self, ang_pos: f32, ang_velocity: f32) {
+        self.sum_pos += ang_pos;
+        self.sum_pos_sq += ang_pos * ang_pos;
+        self.sum_abs_velocity += ang_velocity.abs();
+        self.count += 1;
+    }
+
+    /// `[mean, std]` of angular positions and mean absolute angular velocity
+    pub fn stats(&self) -> [f32; 3] {
+        if self.count == 0 {
+            return [0.0; 3];
+        }
+        let n = self.count as f32;
+        let mean = self.sum_pos / n;
+        let var = (self.sum_pos_sq / n - mean * mean).max(0.0);
+        [mean, var.sqrt(), self.sum_abs_velocity / n]
+    }
 }
 
 /// also contains blobgeno, but been added in `BlobBuilder::update_geno` function
@@ -4,9 +4,14 @@ use std::f32::consts::PI;
 
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
+use rand::rngs::StdRng;
 
 use crate::{
-    brain::neuron::{BlockNN, BrainNN, GenericNN},
+    brain::{
+        neat::Innovations,
+        neuron::{BlockNN, BrainNN, GenericNN},
+    },
+    config::config,
     consts::*,
 };
 
@@ -29,12 +34,16 @@ pub struct BlobBlock {
 }
 
 /// BlobBuilder, takes ownership fo commands and mut reference of nnvec.
+/// New NNs are initialized from the mut reference of rng,
+/// NEAT genes are recorded in `innovations`.
 /// 
 /// Can use it to generate a physical blob with nn in any possible structures
 pub struct BlobBuilder<'a> {
     // tools
     commands: Commands<'a, 'a>,
     nnvec: &'a mut Vec<GenericNN>,
+    innovations: &'a mut Innovations,
+    rng: &'a mut StdRng,
 
     // builder info
     blob_bundle: Entity,
@@ -57,11 +66,18 @@ impl<'a> BlobBuilder<'a> {
     ///
     /// To generate multiple blobs, or want to use BlobBuilder in loops,
     /// please use `clean()` so that there won't be joints connects.
-    pub fn from_commands(mut commands: Commands<'a, 'a>, nnvec: &'a mut Vec<GenericNN>) -> Self {
+    pub fn from_commands(
+        mut commands: Commands<'a, 'a>,
+        nnvec: &'a mut Vec<GenericNN>,
+        innovations: &'a mut Innovations,
+        rng: &'a mut StdRng,
+    ) -> Self {
         Self {
             blob_bundle: commands.spawn(BlobBundle::default()).id(),
             commands: commands,
             nnvec: nnvec,
+            innovations: innovations,
+            rng: rng,
             blocks: Vec::new(),
             current_pos: None,
             info: BlobInfo::default(),
@@ -165,7 +181,7 @@ impl<'a> BlobBuilder<'a> {
         phy_block_bundle: PhysiBlockBundle,
         others: T,
     ) -> Option<usize> {
-        let nn = BrainNN::default();
+        let nn = BrainNN::new_rand(self.innovations, self.rng);
         self.nnvec.push(GenericNN::BRAINNN(nn));
         // push first so the real id should minus one
         let nn_id = self.nnvec.len() - 1;
@@ -232,7 +248,7 @@ impl<'a> BlobBuilder<'a> {
             return None;
         }
 
-        let nn = BlockNN::default();
+        let nn = BlockNN::new_rand(self.innovations, self.rng);
         self.nnvec.push(GenericNN::BLOCKNN(nn));
         let nn_id = self.nnvec.len() - 1;
 
@@ -274,7 +290,7 @@ impl<'a> BlobBuilder<'a> {
         let mut stiff = 0.0;
         let mut motor_target = 0.0;
         if motor_pos.is_some() {
-            stiff = MOTOR_STIFFNESS;
+            stiff = config().physics.motor_stiffness;
             motor_target = motor_pos.unwrap();
         }
 
@@ -287,7 +303,7 @@ impl<'a> BlobBuilder<'a> {
         let joint = RevoluteJointBuilder::new()
             .local_anchor1(block.anchors.left)
             .local_anchor2(new_block.anchors.right)
-            .motor_position(motor_target, stiff, MOTOR_DAMPING)
+            .motor_position(motor_target, stiff, config().physics.motor_damping)
             .limits(limits);
 
         bind_joint(&mut self.commands, block.id, new_block.id, joint);
@@ -325,7 +341,7 @@ impl<'a> BlobBuilder<'a> {
             return None;
         }
 
-        let nn = BlockNN::default();
+        let nn = BlockNN::new_rand(self.innovations, self.rng);
         self.nnvec.push(GenericNN::BLOCKNN(nn));
         let nn_id = self.nnvec.len() - 1;
 
@@ -367,7 +383,7 @@ impl<'a> BlobBuilder<'a> {
         let mut stiff = 0.0;
         let mut motor_target = 0.0;
         if motor_pos.is_some() {
-            stiff = MOTOR_STIFFNESS;
+            stiff = config().physics.motor_stiffness;
             motor_target = motor_pos.unwrap();
         }
 
@@ -380,7 +396,7 @@ impl<'a> BlobBuilder<'a> {
         let joint = RevoluteJointBuilder::new()
             .local_anchor1(block.anchors.right)
             .local_anchor2(new_block.anchors.left)
-            .motor_position(motor_target, stiff, MOTOR_DAMPING)
+            .motor_position(motor_target, stiff, config().physics.motor_damping)
             .limits(limits);
 
         bind_joint(&mut self.commands, block.id, new_block.id, joint);
@@ -418,7 +434,7 @@ impl<'a> BlobBuilder<'a> {
             return None;
         }
 
-        let nn = BlockNN::default();
+        let nn = BlockNN::new_rand(self.innovations, self.rng);
         self.nnvec.push(GenericNN::BLOCKNN(nn));
         let nn_id = self.nnvec.len() - 1;
 
@@ -460,7 +476,7 @@ impl<'a> BlobBuilder<'a> {
         let mut stiff = 0.0;
         let mut motor_target = 0.0;
         if motor_pos.is_some() {
-            stiff = MOTOR_STIFFNESS;
+            stiff = config().physics.motor_stiffness;
             motor_target = motor_pos.unwrap();
         }
 
@@ -473,7 +489,7 @@ impl<'a> BlobBuilder<'a> {
         let joint = RevoluteJointBuilder::new()
             .local_anchor1(block.anchors.top)
             .local_anchor2(new_block.anchors.bottom)
-            .motor_position(motor_target, stiff, MOTOR_DAMPING)
+            .motor_position(motor_target, stiff, config().physics.motor_damping)
             .limits(limits);
 
         bind_joint(&mut self.commands, block.id, new_block.id, joint);
@@ -511,7 +527,7 @@ impl<'a> BlobBuilder<'a> {
             return None;
         }
 
-        let nn = BlockNN::default();
+        let nn = BlockNN::new_rand(self.innovations, self.rng);
         self.nnvec.push(GenericNN::BLOCKNN(nn));
         let nn_id = self.nnvec.len() - 1;
 
@@ -553,7 +569,7 @@ impl<'a> BlobBuilder<'a> {
         let mut stiff = 0.0;
         let mut motor_target = 0.0;
         if motor_pos.is_some() {
-            stiff = MOTOR_STIFFNESS;
+            stiff = config().physics.motor_stiffness;
             motor_target = motor_pos.unwrap();
         }
 
@@ -566,7 +582,7 @@ impl<'a> BlobBuilder<'a> {
         let joint = RevoluteJointBuilder::new()
             .local_anchor1(block.anchors.bottom)
             .local_anchor2(new_block.anchors.top)
-            .motor_position(motor_target, stiff, MOTOR_DAMPING)
+            .motor_position(motor_target, stiff, config().physics.motor_damping)
             .limits(limits);
 
         bind_joint(&mut self.commands, block.id, new_block.id, joint);
@@ -26,6 +26,17 @@ impl BlockAnchors {
             right: Vec2 { x: dx, y: 0.0 },
         }
     }
+
+    /// anchor of a `ParentAnchor` index
+    pub fn of(&self, anchor: usize) -> Vec2 {
+        match anchor {
+            0 => self.top,
+            1 => self.bottom,
+            2 => self.left,
+            3 => self.right,
+            _ => panic!("anchor index must be in 0..=3"),
+        }
+    }
 }
 
 /// BlockDepth is a u32 which represent the depth of the block
@@ -4,15 +4,17 @@ use std::f32::consts::PI;
 use std::fmt::{self, Debug};
 
 use bevy::prelude::*;
-use rand::prelude::*;
+use rand::{prelude::*, rngs::StdRng};
 use serde::{Serialize, Deserialize};
 
 use crate::blob::block::NeuronId;
+use crate::brain::neat::Innovations;
 use crate::brain::neuron::GenericNN;
 use crate::consts::*;
 
 use super::blob_builder::BlobBuilder;
 use super::block::PhysiBlockBundle;
+use super::lineage::Lineage;
 
 /// Generate Blob according to Genotype
 /// Wrapper around BlobBuilder
@@ -21,9 +23,14 @@ pub struct GenoBlobBuilder<'a> {
 }
 
 impl<'a> GenoBlobBuilder<'a> {
-    pub fn from_commands(commands: Commands<'a, 'a>, nnvec: &'a mut Vec<GenericNN>) -> Self {
+    pub fn from_commands(
+        commands: Commands<'a, 'a>,
+        nnvec: &'a mut Vec<GenericNN>,
+        innovations: &'a mut Innovations,
+        rng: &'a mut StdRng,
+    ) -> Self {
         Self {
-            builder: BlobBuilder::from_commands(commands, nnvec),
+            builder: BlobBuilder::from_commands(commands, nnvec, innovations, rng),
         }
     }
 
@@ -258,12 +265,16 @@ fn build_node_with_nn(
 #[derive(Debug, Component, Clone, Serialize, Deserialize)]
 pub struct BlobGeno {
     pub vec_tree: QuadTree<GenericGenoNode>,
+    /// ancestry and mutation history, not part of the morphology
+    #[serde(default)]
+    pub lineage: Lineage,
 }
 
 impl Default for BlobGeno {
     fn default() -> Self {
         Self {
             vec_tree: QuadTree::<GenericGenoNode>::new(GENO_MAX_DEPTH),
+            lineage: Lineage::default(),
         }
     }
 }
@@ -271,7 +282,7 @@ impl Default for BlobGeno {
 impl BlobGeno {
     // TODO: Clean the code. Ugly long function
     /// generate a random GenoType that don't have conflict limbs
-    pub fn new_rand() -> BlobGeno {
+    pub fn new_rand(rng: &mut impl Rng) -> BlobGeno {
         // prevent tree-structural block conflict
         let mut occupied_region = Vec::<[f32; 4]>::new();
 
@@ -302,9 +313,8 @@ impl BlobGeno {
             parent: &GenoNode,
             direction: usize,
             occupied_region: &mut Vec<[f32; 4]>,
+            rng: &mut impl Rng,
         ) -> Option<GenericGenoNode> {
-            let mut rng = thread_rng();
-
             let parent_size = parent.size;
             let parent_center = parent.center;
 
@@ -377,9 +387,8 @@ impl BlobGeno {
             tree: &mut QuadTree<GenericGenoNode>,
             index: usize,
             occupied_region: &mut Vec<[f32; 4]>,
+            rng: &mut impl Rng,
         ) {
-            let mut rng = thread_rng();
-
             let children = tree.children(index);
 
             // index and children index should in range
@@ -390,17 +399,17 @@ impl BlobGeno {
             // random init four nodes, avoid self-conflict
             if let Some(GenericGenoNode::Child(node)) = tree.nodes[index].clone() {
                 for (i, &child) in children.iter().enumerate() {
-                    tree.nodes[child] = rand_nodes(&node, i, occupied_region)
+                    tree.nodes[child] = rand_nodes(&node, i, occupied_region, rng)
                 }
 
                 // one parent indicator
-                let parent_idx = *children.choose(&mut rng).unwrap();
+                let parent_idx = *children.choose(rng).unwrap();
                 tree.nodes[parent_idx] = Some(GenericGenoNode::Parent);
 
                 // keep recursion
                 for &i in children.iter() {
                     if i != parent_idx {
-                        build(tree, i, occupied_region);
+                        build(tree, i, occupied_region, rng);
                     }
                 }
             }
@@ -410,7 +419,7 @@ impl BlobGeno {
         let mut bg = BlobGeno::default();
         // root node
         bg.vec_tree.nodes[0] = Some(GenericGenoNode::Child(GenoNode::default()));
-        build(&mut bg.vec_tree, 0, &mut occupied_region);
+        build(&mut bg.vec_tree, 0, &mut occupied_region, rng);
         bg
     }
 
@@ -536,6 +545,20 @@ impl BlobGeno {
             .collect()
     }
 
+    /// copy the geno and the NNs it uses out of `nnvec`,
+    /// nn_id of the copied geno index the returned vector
+    pub fn detach_nn(&self, nnvec: &[GenericNN]) -> (BlobGeno, Vec<GenericNN>) {
+        let mut geno = self.clone();
+        let mut own_nnvec = Vec::<GenericNN>::new();
+        for nn_id in geno.all_nn_ids_mut() {
+            if let Some(id) = nn_id {
+                own_nnvec.push(nnvec[*id].clone());
+                *nn_id = Some(own_nnvec.len() - 1);
+            }
+        }
+        (geno, own_nnvec)
+    }
+
     pub fn all_nn_ids_indices(&self) -> Vec<usize> {
         self.vec_tree.nodes.iter().enumerate()
             .filter_map(|(idx, node_option)| {
@@ -961,9 +984,21 @@ mod builder_validation_test {
 
     #[test]
     fn test_geno_builder_validation() {
+        let mut rng = thread_rng();
         for _ in 0..100 {
-            let geno = BlobGeno::new_rand();
+            let geno = BlobGeno::new_rand(&mut rng);
             assert!(geno.is_valid());
         }
     }
+
+    #[test]
+    fn test_geno_same_seed() {
+        let mut rng_a = StdRng::seed_from_u64(42);
+        let mut rng_b = StdRng::seed_from_u64(42);
+        for _ in 0..10 {
+            let geno_a = serde_json::to_string(&BlobGeno::new_rand(&mut rng_a)).unwrap();
+            let geno_b = serde_json::to_string(&BlobGeno::new_rand(&mut rng_b)).unwrap();
+            assert_eq!(geno_a, geno_b);
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,39 @@
+//! lineage of a blob, who it descended from and how it was mutated
+
+use serde::{Deserialize, Serialize};
+
+/// ancestry information stored with each `BlobGeno`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Lineage {
+    /// unique blob id, `None` until the blob is evaluated the first time.
+    ///
+    /// A blob keeps its id as long as it survives
+    pub id: Option<u64>,
+    /// one parent for a clone, two for a crossover
+    pub parents: Vec<u64>,
+    /// mutations since the last evaluation
+    pub mutations: Vec<MutationEvent>,
+}
+
+impl Lineage {
+    /// lineage of a new blob, id is assigned when it is evaluated
+    pub fn child_of(parents: Vec<u64>) -> Self {
+        Self {
+            id: None,
+            parents,
+            mutations: Vec::new(),
+        }
+    }
+}
+
+/// a mutation applied to a blob, `index` is the node index in geno tree
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MutationEvent {
+    GainLimb { index: usize },
+    LoseLimb { index: usize },
+    BlockSize { index: usize },
+    JointLimit { index: usize },
+    /// weights of `blocks` NNs are perturbed
+    Nn { blocks: usize },
+}
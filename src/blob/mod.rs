@@ -3,4 +3,5 @@
 pub mod block;
 pub mod blob_builder;
 pub mod blob;
-pub mod geno_blob_builder;
\ No newline at end of file
+pub mod geno_blob_builder;
+pub mod lineage;
\ No newline at end of file
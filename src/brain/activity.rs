@@ -0,0 +1,202 @@
+//! activity recorder of block networks, to see what a controller is doing.
+//!
+//! Recording is off by default, see `DebugConfig::record_activity`.
+
+use std::{collections::VecDeque, error::Error, fmt::Write as _, fs, ops::Range, path::Path};
+
+use bevy::prelude::*;
+use serde::Serialize;
+
+/// inputs and outputs of a block in one frame
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockActivity {
+    pub frame: u128,
+    pub blob: Entity,
+    pub block: Entity,
+    pub inward_input: Vec<f32>,
+    pub inward_output: Vec<f32>,
+    pub outward_output: Vec<f32>,
+    pub target_pos: f32,
+    pub target_vel: f32,
+}
+
+/// records of the last `window` frames
+#[derive(Debug, Default)]
+pub struct ActivityRecorder {
+    enabled: bool,
+    window: u128,
+    records: VecDeque<BlockActivity>,
+    /// records of the current frame keyed by joint entity,
+    /// waiting for `commit` to fill in frame and entities
+    pending: Vec<(Entity, BlockActivity)>,
+}
+
+impl ActivityRecorder {
+    pub fn new(enabled: bool, window: u128) -> Self {
+        Self {
+            enabled,
+            window,
+            ..default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// turning recording off drops all records
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.records.clear();
+            self.pending.clear();
+        }
+    }
+
+    pub(crate) fn push_pending(
+        &mut self,
+        joint: Entity,
+        inward_input: Vec<f32>,
+        inward_output: Vec<f32>,
+        outward_output: Vec<f32>,
+        target: (f32, f32),
+    ) {
+        self.pending.push((
+            joint,
+            BlockActivity {
+                frame: 0,
+                blob: Entity::PLACEHOLDER,
+                block: Entity::PLACEHOLDER,
+                inward_input,
+                inward_output,
+                outward_output,
+                target_pos: target.0,
+                target_vel: target.1,
+            },
+        ));
+    }
+
+    /// store records of this frame and drop the ones out of the window.
+    ///
+    /// `owner` gives the block and blob entity of a joint entity,
+    /// records without owner are dropped
+    pub fn commit(&mut self, frame: u128, owner: impl Fn(Entity) -> Option<(Entity, Entity)>) {
+        for (joint, mut record) in self.pending.drain(..) {
+            let Some((block, blob)) = owner(joint) else {
+                continue;
+            };
+            record.frame = frame;
+            record.block = block;
+            record.blob = blob;
+            self.records.push_back(record);
+        }
+        while let Some(first) = self.records.front() {
+            if first.frame + self.window > frame {
+                break;
+            }
+            self.records.pop_front();
+        }
+    }
+
+    pub fn of_blob(&self, blob: Entity) -> impl Iterator<Item = &BlockActivity> {
+        self.records.iter().filter(move |r| r.blob == blob)
+    }
+
+    pub fn of_block(&self, block: Entity) -> impl Iterator<Item = &BlockActivity> {
+        self.records.iter().filter(move |r| r.block == block)
+    }
+
+    /// records of `blob` in `frames`, all blobs if `blob` is `None`
+    pub fn select(&self, blob: Option<Entity>, frames: Range<u128>) -> Vec<&BlockActivity> {
+        self.records
+            .iter()
+            .filter(|r| blob.map_or(true, |blob| r.blob == blob) && frames.contains(&r.frame))
+            .collect()
+    }
+
+    /// write the selected records to `path`, JSON if it ends with `.json`, CSV otherwise
+    pub fn dump(
+        &self,
+        blob: Option<Entity>,
+        frames: Range<u128>,
+        path: &Path,
+    ) -> Result<(), Box<dyn Error>> {
+        let records = self.select(blob, frames);
+        let file_str = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::to_string(&records)?,
+            _ => to_csv(&records),
+        };
+        if let Some(folder) = path.parent() {
+            fs::create_dir_all(folder)?;
+        }
+        fs::write(path, file_str)?;
+        Ok(())
+    }
+}
+
+/// one row per record, vectors are spread into numbered columns
+pub fn to_csv(records: &[&BlockActivity]) -> String {
+    let mut csv = String::new();
+    let Some(first) = records.first() else {
+        return csv;
+    };
+
+    csv.push_str("frame,blob,block,target_pos,target_vel");
+    for (name, len) in [
+        ("inward_input", first.inward_input.len()),
+        ("inward_output", first.inward_output.len()),
+        ("outward_output", first.outward_output.len()),
+    ] {
+        for i in 0..len {
+            write!(csv, ",{}_{}", name, i).unwrap();
+        }
+    }
+    csv.push('\n');
+
+    for r in records.iter() {
+        write!(
+            csv,
+            "{},{},{},{},{}",
+            r.frame,
+            r.blob.to_bits(),
+            r.block.to_bits(),
+            r.target_pos,
+            r.target_vel
+        )
+        .unwrap();
+        for x in r.inward_input.iter().chain(&r.inward_output).chain(&r.outward_output) {
+            write!(csv, ",{}", x).unwrap();
+        }
+        csv.push('\n');
+    }
+    csv
+}
+
+#[cfg(test)]
+mod activity_test {
+    use super::*;
+
+    #[test]
+    fn test_window() {
+        let joint = Entity::from_raw(3);
+        let owner = |_| Some((Entity::from_raw(2), Entity::from_raw(1)));
+        let mut recorder = ActivityRecorder::new(true, 2);
+        for frame in 0..5 {
+            recorder.push_pending(joint, vec![1.0], vec![2.0, 3.0], vec![4.0], (0.5, -0.5));
+            recorder.commit(frame, owner);
+        }
+        // only frame 3 and 4 are kept
+        let records = recorder.select(Some(Entity::from_raw(1)), 0..10);
+        assert_eq!(records.iter().map(|r| r.frame).collect::<Vec<_>>(), vec![3, 4]);
+        assert_eq!(recorder.of_block(Entity::from_raw(2)).count(), 2);
+
+        let csv = to_csv(&records);
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next().unwrap(),
+            "frame,blob,block,target_pos,target_vel,\
+             inward_input_0,inward_output_0,inward_output_1,outward_output_0"
+        );
+        assert!(lines.next().unwrap().ends_with(",0.5,-0.5,1,2,3,4"));
+    }
+}
@@ -1,6 +1,9 @@
 //! all implementation about neural networks
 
+pub mod activity;
 pub mod resource;
 pub mod neuron;
 pub mod signal;
-pub mod nn;
\ No newline at end of file
+pub mod neat;
+pub mod nn;
+pub mod oscillator;
\ No newline at end of file
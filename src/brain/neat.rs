@@ -0,0 +1,482 @@
+//! NEAT-style network, whose topology evolves together with its weights.
+//!
+//! Genes are nodes and connections. Connections carry innovation numbers,
+//! the same structural mutation gets the same number in every network,
+//! so that crossover can align the genes of two parents.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use bevy::prelude::Resource;
+use ndarray::prelude::*;
+use rand::{distributions::Uniform, prelude::*};
+use serde::{Deserialize, Serialize};
+
+use super::nn::Activation;
+
+/// max tries to find two nodes which can be connected
+const ADD_CONNECTION_MAX_TRY: usize = 20;
+
+/// Bevy resource recording the structural mutations of a run.
+///
+/// Innovation numbers are not saved, networks loaded from files are `observe`d instead
+#[derive(Resource, Default)]
+pub struct Innovations {
+    /// `(from, to)` to innovation number
+    connections: BTreeMap<(usize, usize), usize>,
+    /// innovation of the split connection to id of the new node
+    splits: BTreeMap<usize, usize>,
+    next_innovation: usize,
+    next_node: usize,
+}
+
+impl Innovations {
+    /// register genes of a network, networks loaded from file may use unknown numbers
+    pub fn observe(&mut self, nn: &NeatNN) {
+        for node in nn.nodes.iter() {
+            self.next_node = self.next_node.max(node.id + 1);
+        }
+        for conn in nn.connections.iter() {
+            self.connections.entry((conn.from, conn.to)).or_insert(conn.innovation);
+            self.next_innovation = self.next_innovation.max(conn.innovation + 1);
+        }
+    }
+
+    fn connection(&mut self, from: usize, to: usize) -> usize {
+        if let Some(&innovation) = self.connections.get(&(from, to)) {
+            return innovation;
+        }
+        let innovation = self.next_innovation;
+        self.next_innovation += 1;
+        self.connections.insert((from, to), innovation);
+        innovation
+    }
+
+    /// id of the node splitting connection `innovation`,
+    /// a fresh id if the recorded one is `taken` in this network
+    fn split(&mut self, innovation: usize, taken: impl Fn(usize) -> bool) -> usize {
+        if let Some(&id) = self.splits.get(&innovation) {
+            if !taken(id) {
+                return id;
+            }
+        }
+        let id = self.next_node;
+        self.next_node += 1;
+        self.splits.entry(innovation).or_insert(id);
+        id
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+    Input,
+    Output,
+    Hidden,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeGene {
+    pub id: usize,
+    pub kind: NodeKind,
+    pub bias: f32,
+    pub activation: Activation,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionGene {
+    pub innovation: usize,
+    pub from: usize,
+    pub to: usize,
+    pub weight: f32,
+    pub enabled: bool,
+}
+
+/// evaluation order, built on the first forward after a structure change
+#[derive(Debug, Clone)]
+struct Plan {
+    /// index of hidden and output nodes, each after all its sources
+    order: Vec<usize>,
+    /// `(source node index, connection index)` of enabled connections into each node
+    incoming: Vec<Vec<(usize, usize)>>,
+}
+
+/// feed-forward network of node and connection genes.
+///
+/// `nodes` starts with the inputs and the outputs, their ids equal their indices,
+/// hidden nodes are appended by `add_node`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NeatNN {
+    pub inputs: usize,
+    pub outputs: usize,
+    /// activation of new hidden nodes
+    pub hidden_activation: Activation,
+    pub nodes: Vec<NodeGene>,
+    pub connections: Vec<ConnectionGene>,
+    #[serde(skip)]
+    plan: Option<Plan>,
+}
+
+impl NeatNN {
+    /// minimal network, every input connects to every output
+    pub fn new_rand(
+        inputs: usize,
+        outputs: usize,
+        hidden_activation: Activation,
+        output_activation: Activation,
+        innovations: &mut Innovations,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let dist = Uniform::new(-1.0, 1.0);
+        let mut nodes = Vec::<NodeGene>::new();
+        for id in 0..inputs {
+            nodes.push(NodeGene {
+                id,
+                kind: NodeKind::Input,
+                bias: 0.0,
+                activation: Activation::Identity,
+            });
+        }
+        for id in inputs..inputs + outputs {
+            nodes.push(NodeGene {
+                id,
+                kind: NodeKind::Output,
+                bias: dist.sample(rng),
+                activation: output_activation.clone(),
+            });
+        }
+
+        let mut connections = Vec::<ConnectionGene>::new();
+        for from in 0..inputs {
+            for to in inputs..inputs + outputs {
+                connections.push(ConnectionGene {
+                    innovation: innovations.connection(from, to),
+                    from,
+                    to,
+                    weight: dist.sample(rng),
+                    enabled: true,
+                });
+            }
+        }
+        innovations.next_node = innovations.next_node.max(inputs + outputs);
+
+        Self {
+            inputs,
+            outputs,
+            hidden_activation,
+            nodes,
+            connections,
+            plan: None,
+        }
+    }
+
+    pub fn forward(&mut self, input: Array1<f32>) -> Array1<f32> {
+        assert_eq!(input.len(), self.inputs);
+        if self.plan.is_none() {
+            self.plan = Some(self.build_plan());
+        }
+        let plan = self.plan.as_ref().unwrap();
+
+        let mut values = vec![0.0; self.nodes.len()];
+        for (value, x) in values.iter_mut().zip(input.iter()) {
+            *value = *x;
+        }
+        for &idx in plan.order.iter() {
+            let node = &self.nodes[idx];
+            let z: f32 = plan.incoming[idx]
+                .iter()
+                .map(|&(src, conn)| values[src] * self.connections[conn].weight)
+                .sum();
+            values[idx] = node.activation.apply(z + node.bias);
+        }
+        Array1::from_iter(values[self.inputs..self.inputs + self.outputs].iter().cloned())
+    }
+
+    /// split a random enabled connection with a new hidden node.
+    ///
+    /// The old connection is disabled, the new node passes its signal on with weight `1.0`.
+    /// Return `false` if there is no connection to split
+    pub fn add_node(&mut self, innovations: &mut Innovations, rng: &mut impl Rng) -> bool {
+        let enabled: Vec<usize> = (0..self.connections.len())
+            .filter(|&i| self.connections[i].enabled)
+            .collect();
+        let Some(&conn_idx) = enabled.choose(rng) else {
+            return false;
+        };
+
+        innovations.observe(self);
+        let ConnectionGene {
+            innovation,
+            from,
+            to,
+            weight,
+            ..
+        } = self.connections[conn_idx].clone();
+        let id = innovations.split(innovation, |id| self.node_index(id).is_some());
+
+        self.connections[conn_idx].enabled = false;
+        self.nodes.push(NodeGene {
+            id,
+            kind: NodeKind::Hidden,
+            bias: 0.0,
+            activation: self.hidden_activation.clone(),
+        });
+        for (from, to, weight) in [(from, id, 1.0), (id, to, weight)] {
+            self.connections.push(ConnectionGene {
+                innovation: innovations.connection(from, to),
+                from,
+                to,
+                weight,
+                enabled: true,
+            });
+        }
+        self.plan = None;
+        true
+    }
+
+    /// connect two random unconnected nodes, without making a cycle.
+    ///
+    /// Return `false` if no such pair is found
+    pub fn add_connection(&mut self, innovations: &mut Innovations, rng: &mut impl Rng) -> bool {
+        let dist = Uniform::new(-1.0, 1.0);
+        for _ in 0..ADD_CONNECTION_MAX_TRY {
+            let from = self.nodes.choose(rng).unwrap();
+            let to = self.nodes.choose(rng).unwrap();
+            if from.kind == NodeKind::Output || to.kind == NodeKind::Input {
+                continue;
+            }
+            let (from, to) = (from.id, to.id);
+            if from == to
+                || self.connections.iter().any(|c| c.from == from && c.to == to)
+                || self.reaches(to, from)
+            {
+                continue;
+            }
+
+            innovations.observe(self);
+            self.connections.push(ConnectionGene {
+                innovation: innovations.connection(from, to),
+                from,
+                to,
+                weight: dist.sample(rng),
+                enabled: true,
+            });
+            self.plan = None;
+            return true;
+        }
+        false
+    }
+
+    /// change the number of inputs and outputs, return `true` if they changed.
+    ///
+    /// Kept nodes keep their genes, connections of dropped nodes are removed,
+    /// new inputs and outputs start unconnected.
+    /// Hidden node ids are shifted if the new inputs and outputs need them
+    pub fn migrate(&mut self, inputs: usize, outputs: usize) -> bool {
+        if self.inputs == inputs && self.outputs == outputs {
+            return false;
+        }
+        let old_io = self.inputs + self.outputs;
+        let offset = (inputs + outputs).saturating_sub(old_io);
+        let old_inputs = self.inputs;
+        let new_id = |id: usize| -> Option<usize> {
+            if id < old_inputs {
+                (id < inputs).then_some(id)
+            } else if id < old_io {
+                (id - old_inputs < outputs).then_some(inputs + id - old_inputs)
+            } else {
+                Some(id + offset)
+            }
+        };
+
+        let output_activation = self.nodes[old_inputs..old_io]
+            .first()
+            .map_or(self.hidden_activation.clone(), |node| node.activation.clone());
+        let mut nodes = Vec::<NodeGene>::new();
+        for id in 0..inputs {
+            nodes.push(match self.nodes[..old_inputs].get(id) {
+                Some(node) => node.clone(),
+                None => NodeGene {
+                    id,
+                    kind: NodeKind::Input,
+                    bias: 0.0,
+                    activation: Activation::Identity,
+                },
+            });
+        }
+        for j in 0..outputs {
+            let template = self.nodes[old_inputs..old_io].get(j);
+            nodes.push(NodeGene {
+                id: inputs + j,
+                kind: NodeKind::Output,
+                bias: template.map_or(0.0, |node| node.bias),
+                activation: template.map_or(output_activation.clone(), |node| {
+                    node.activation.clone()
+                }),
+            });
+        }
+        for node in self.nodes[old_io..].iter() {
+            nodes.push(NodeGene {
+                id: node.id + offset,
+                ..node.clone()
+            });
+        }
+
+        self.connections = self
+            .connections
+            .iter()
+            .filter_map(|conn| {
+                Some(ConnectionGene {
+                    from: new_id(conn.from)?,
+                    to: new_id(conn.to)?,
+                    ..conn.clone()
+                })
+            })
+            .collect();
+        self.nodes = nodes;
+        self.inputs = inputs;
+        self.outputs = outputs;
+        self.plan = None;
+        true
+    }
+
+    pub fn node_index(&self, id: usize) -> Option<usize> {
+        self.nodes.iter().position(|node| node.id == id)
+    }
+
+    /// whether there is a path from `from` to `to`, disabled connections included
+    fn reaches(&self, from: usize, to: usize) -> bool {
+        let mut stack = vec![from];
+        let mut visited = Vec::<usize>::new();
+        while let Some(id) = stack.pop() {
+            if id == to {
+                return true;
+            }
+            if visited.contains(&id) {
+                continue;
+            }
+            visited.push(id);
+            stack.extend(self.connections.iter().filter(|c| c.from == id).map(|c| c.to));
+        }
+        false
+    }
+
+    /// topological order of enabled connections (Kahn's algorithm).
+    ///
+    /// Nodes on a cycle, which only a broken file can have, are left out and output `0.0`
+    fn build_plan(&self) -> Plan {
+        let index: HashMap<usize, usize> =
+            self.nodes.iter().enumerate().map(|(i, node)| (node.id, i)).collect();
+        let mut incoming = vec![Vec::<(usize, usize)>::new(); self.nodes.len()];
+        let mut outgoing = vec![Vec::<usize>::new(); self.nodes.len()];
+        for (conn_idx, conn) in self.connections.iter().enumerate() {
+            if !conn.enabled {
+                continue;
+            }
+            if let (Some(&from), Some(&to)) = (index.get(&conn.from), index.get(&conn.to)) {
+                incoming[to].push((from, conn_idx));
+                outgoing[from].push(to);
+            }
+        }
+
+        let mut in_degree: Vec<usize> = incoming.iter().map(|v| v.len()).collect();
+        let mut ready: Vec<usize> = (0..self.nodes.len()).filter(|&i| in_degree[i] == 0).collect();
+        let mut order = Vec::<usize>::new();
+        while let Some(idx) = ready.pop() {
+            if self.nodes[idx].kind != NodeKind::Input {
+                order.push(idx);
+            }
+            for &next in outgoing[idx].iter() {
+                in_degree[next] -= 1;
+                if in_degree[next] == 0 {
+                    ready.push(next);
+                }
+            }
+        }
+        Plan { order, incoming }
+    }
+}
+
+impl fmt::Display for NeatNN {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "NEAT Network:\nInputs: {}, Outputs: {}, Hidden: {}\nConnections: {} ({} enabled)",
+            self.inputs,
+            self.outputs,
+            self.nodes.len() - self.inputs - self.outputs,
+            self.connections.len(),
+            self.connections.iter().filter(|c| c.enabled).count()
+        )
+    }
+}
+
+#[cfg(test)]
+mod neat_test {
+    use rand::rngs::StdRng;
+
+    use super::*;
+
+    #[test]
+    fn test_structural_mutation() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut innovations = Innovations::default();
+        let (hidden, output) = (Activation::Tanh, Activation::Sigmoid);
+        let mut nn = NeatNN::new_rand(3, 2, hidden, output, &mut innovations, &mut rng);
+        let input = Array1::from_vec(vec![0.5, -0.5, 1.0]);
+        assert_eq!(nn.forward(input.clone()).len(), 2);
+
+        for _ in 0..10 {
+            assert!(nn.add_node(&mut innovations, &mut rng));
+            nn.add_connection(&mut innovations, &mut rng);
+        }
+        assert_eq!(nn.nodes.len(), 15);
+        let output = nn.forward(input);
+        assert_eq!(output.len(), 2);
+        assert!(output.iter().all(|x| x.is_finite()));
+        // every hidden and output node can be evaluated, so there is no cycle
+        assert_eq!(nn.plan.as_ref().unwrap().order.len(), 12);
+    }
+
+    #[test]
+    fn test_same_innovation() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut innovations = Innovations::default();
+        let (hidden, output) = (Activation::Tanh, Activation::Tanh);
+        let mut a = NeatNN::new_rand(2, 1, hidden, output, &mut innovations, &mut rng);
+        let mut b = a.clone();
+        for conn in b.connections.iter_mut() {
+            conn.weight = 0.0;
+        }
+        // both networks split the same connection
+        a.connections.truncate(1);
+        b.connections.truncate(1);
+        a.add_node(&mut innovations, &mut rng);
+        b.add_node(&mut innovations, &mut rng);
+        assert_eq!(a.nodes[3].id, 3);
+        assert_eq!(b.nodes[3].id, 3);
+        let numbers =
+            |nn: &NeatNN| -> Vec<usize> { nn.connections.iter().map(|c| c.innovation).collect() };
+        assert_eq!(numbers(&a), vec![0, 2, 3]);
+        assert_eq!(numbers(&b), vec![0, 2, 3]);
+    }
+
+    #[test]
+    fn test_observe_loaded() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (hidden, output) = (Activation::Tanh, Activation::Tanh);
+        let mut nn = NeatNN::new_rand(2, 1, hidden, output, &mut Innovations::default(), &mut rng);
+        // as if loaded from a file saved by a longer run
+        nn.connections[0].innovation = 10;
+
+        let mut innovations = Innovations::default();
+        innovations.observe(&nn);
+        nn.connections.truncate(1);
+        nn.add_node(&mut innovations, &mut rng);
+        let numbers: Vec<usize> = nn.connections.iter().map(|c| c.innovation).collect();
+        assert_eq!(numbers, vec![10, 11, 12]);
+        assert_eq!(nn.nodes[3].id, 3);
+    }
+}
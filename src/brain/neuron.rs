@@ -6,10 +6,15 @@ use ndarray::prelude::*;
 use rand::prelude::*;
 use serde::{Serialize, Deserialize};
 
-use crate::consts::*;
+use crate::{
+    config::{config, SimConfig},
+    consts::*,
+};
 
 use super::{
+    neat::{Innovations, NeatNN},
     nn::BaseNN,
+    oscillator::Oscillator,
     signal::{BrainSignal, InwardNNInputSignal, OutwardNNInputSignal},
 };
 
@@ -22,34 +27,139 @@ pub enum GenericNN {
     BRAINNN(BrainNN),
 }
 
+impl GenericNN {
+    /// clear recurrent state of all the networks
+    pub fn reset_state(&mut self) {
+        match self {
+            GenericNN::BLOCKNN(nn) => {
+                nn.inward_nn.nn.reset_state();
+                nn.outward_nn.nn.reset_state();
+                if let Some(oscillator) = &mut nn.oscillator {
+                    oscillator.reset_state();
+                }
+            }
+            GenericNN::BRAINNN(nn) => nn.nn.reset_state(),
+        }
+    }
+
+    /// migrate networks whose shape differs from the config, see `BaseNN::migrate`.
+    ///
+    /// Return `true` if any network was migrated
+    pub fn migrate(&mut self, config: &SimConfig) -> bool {
+        match self {
+            GenericNN::BLOCKNN(nn) => {
+                let inward = nn.inward_nn.nn.migrate(&config.inward_shape());
+                let outward = nn.outward_nn.nn.migrate(&config.outward_shape());
+                inward || outward
+            }
+            GenericNN::BRAINNN(nn) => nn.nn.migrate(&config.brain_shape()),
+        }
+    }
+
+    /// register NEAT genes of loaded networks, see `Innovations::observe`
+    pub fn observe_innovations(&self, innovations: &mut Innovations) {
+        match self {
+            GenericNN::BLOCKNN(nn) => {
+                nn.inward_nn.nn.observe_innovations(innovations);
+                nn.outward_nn.nn.observe_innovations(innovations);
+            }
+            GenericNN::BRAINNN(nn) => nn.nn.observe_innovations(innovations),
+        }
+    }
+}
+
+/// network with fixed layers, or with evolving topology if `NNConfig::neat` is set
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AnyNN {
+    Base(BaseNN),
+    Neat(NeatNN),
+}
+
+impl AnyNN {
+    /// `shape` is `[input, hidden..., output]`, NEAT networks start without hidden nodes
+    pub fn new_rand(shape: Vec<usize>, innovations: &mut Innovations, rng: &mut impl Rng) -> Self {
+        let nn_config = &config().nn;
+        if nn_config.neat {
+            AnyNN::Neat(NeatNN::new_rand(
+                shape[0],
+                *shape.last().unwrap(),
+                nn_config.activation.clone(),
+                nn_config.output_activation.clone(),
+                innovations,
+                rng,
+            ))
+        } else {
+            AnyNN::Base(BaseNN::new_rand(
+                shape,
+                nn_config.activation.clone(),
+                nn_config.output_activation.clone(),
+                nn_config.recurrent,
+                rng,
+            ))
+        }
+    }
+
+    pub fn forward(&mut self, input: Array1<f32>) -> Array1<f32> {
+        match self {
+            AnyNN::Base(nn) => nn.forward(input),
+            AnyNN::Neat(nn) => nn.forward(input),
+        }
+    }
+
+    /// NEAT networks are feed-forward and have no state
+    pub fn reset_state(&mut self) {
+        if let AnyNN::Base(nn) = self {
+            nn.reset_state();
+        }
+    }
+
+    /// NEAT networks only migrate their inputs and outputs
+    pub fn migrate(&mut self, shape: &[usize]) -> bool {
+        match self {
+            AnyNN::Base(nn) => nn.migrate(shape),
+            AnyNN::Neat(nn) => nn.migrate(shape[0], *shape.last().unwrap()),
+        }
+    }
+
+    pub fn observe_innovations(&self, innovations: &mut Innovations) {
+        if let AnyNN::Neat(nn) = self {
+            innovations.observe(nn);
+        }
+    }
+}
+
+impl fmt::Display for AnyNN {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnyNN::Base(nn) => write!(f, "{}", nn),
+            AnyNN::Neat(nn) => write!(f, "{}", nn),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InwardNN {
-    pub nn: BaseNN,
+    pub nn: AnyNN,
 }
 
-impl Default for InwardNN {
-    fn default() -> Self {
+impl InwardNN {
+    pub fn new_rand(innovations: &mut Innovations, rng: &mut impl Rng) -> Self {
         Self {
-            nn: BaseNN::new_rand(
-                Vec::from_iter(INWARD_NN_SHAPE.into_iter().clone()),
-                ACTIVATION_FUNCTION,
-            ),
+            nn: AnyNN::new_rand(config().inward_shape(), innovations, rng),
         }
     }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OutwardNN {
-    pub nn: BaseNN,
+    pub nn: AnyNN,
 }
 
-impl Default for OutwardNN {
-    fn default() -> Self {
+impl OutwardNN {
+    pub fn new_rand(innovations: &mut Innovations, rng: &mut impl Rng) -> Self {
         Self {
-            nn: BaseNN::new_rand(
-                Vec::from_iter(OUTWARD_NN_SHAPE.into_iter().clone()),
-                ACTIVATION_FUNCTION,
-            ),
+            nn: AnyNN::new_rand(config().outward_shape(), innovations, rng),
         }
     }
 }
@@ -58,76 +168,90 @@ impl Default for OutwardNN {
 ///
 /// Each block should have two independent neurons:
 /// InwardNN and OutwardNN
+///
+/// With `NNConfig::oscillator`, the oscillator output is the last input of both
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockNN {
     pub inward_nn: InwardNN,
     pub outward_nn: OutwardNN,
     pub outward_signal: OutwardNNInputSignal,
+    #[serde(default)]
+    pub oscillator: Option<Oscillator>,
 }
 
-impl Default for BlockNN {
-    fn default() -> Self {
+impl BlockNN {
+    pub fn new_rand(innovations: &mut Innovations, rng: &mut impl Rng) -> Self {
         Self {
-            inward_nn: InwardNN::default(),
-            outward_nn: OutwardNN::default(),
+            inward_nn: InwardNN::new_rand(innovations, rng),
+            outward_nn: OutwardNN::new_rand(innovations, rng),
             outward_signal: OutwardNNInputSignal::default(),
+            oscillator: config().nn.oscillator.then(|| Oscillator::new_rand(rng)),
         }
     }
-}
 
-impl BlockNN {
-    /// forward function for inward nn
+    /// append the oscillator output to NN input if oscillators are enabled,
+    /// NNs loaded without oscillator get `0.0`
+    fn with_oscillator(&self, input: Array1<f32>) -> Array1<f32> {
+        if !config().nn.oscillator {
+            return input;
+        }
+        let output = self.oscillator.as_ref().map_or(0.0, |o| o.output());
+        input.into_iter().chain(std::iter::once(output)).collect()
+    }
+
+    /// input array of inward nn
     ///
     /// also update the `inherited` element in outward nn
-    fn inward_forward(&mut self, signal: &InwardNNInputSignal) -> Array1<f32> {
+    pub fn inward_input(&mut self, signal: &InwardNNInputSignal) -> Array1<f32> {
         let array_signal = signal.to_array();
         // save duplicate signals for ourward usage
         self.outward_signal.inherit(&array_signal);
-        self.inward_nn.nn.forward(array_signal.clone())
+        self.with_oscillator(array_signal)
     }
 
     /// output inward signal that passing to next layer
     /// Takes input layer's singal
     pub fn get_inward_output(&mut self, signal: &InwardNNInputSignal) -> Array1<f32> {
-        self.inward_forward(signal)
+        let input = self.inward_input(signal);
+        self.inward_nn.nn.forward(input)
     }
 
-    pub fn get_rand_inward_output(&self) -> Array1<f32> {
-        let mut rng = thread_rng();
+    pub fn get_rand_inward_output(&self, rng: &mut impl Rng) -> Array1<f32> {
         Array1::from_shape_fn((4,), |_| rng.gen::<f32>())
     }
 
-    pub fn get_outward_output(&mut self, parent_signal: &Array1<f32>) -> Array1<f32> {
+    /// input array of outward nn, `parent_signal` is the outward output of parent
+    pub fn outward_input(&mut self, parent_signal: &Array1<f32>) -> Array1<f32> {
         assert_eq!(parent_signal.len(), DL);
         self.outward_signal.parent_input = parent_signal.clone();
-        self.outward_nn.nn.forward(self.outward_signal.to_array())
+        let signal = self.outward_signal.to_array();
+        self.with_oscillator(signal)
+    }
+
+    pub fn get_outward_output(&mut self, parent_signal: &Array1<f32>) -> Array1<f32> {
+        let input = self.outward_input(parent_signal);
+        self.outward_nn.nn.forward(input)
     }
 }
 
 /// NN for centeral brain
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BrainNN {
-    pub nn: BaseNN,
+    pub nn: AnyNN,
 }
 
-impl Default for BrainNN {
-    fn default() -> Self {
+impl BrainNN {
+    pub fn new_rand(innovations: &mut Innovations, rng: &mut impl Rng) -> Self {
         Self {
-            nn: BaseNN::new_rand(
-                Vec::from_iter(BRAIN_NN_SHAPE.into_iter().clone()),
-                ACTIVATION_FUNCTION,
-            ),
+            nn: AnyNN::new_rand(config().brain_shape(), innovations, rng),
         }
     }
-}
 
-impl BrainNN {
-    pub fn forward(&self, signal: &BrainSignal) -> Array1<f32> {
+    pub fn forward(&mut self, signal: &BrainSignal) -> Array1<f32> {
         self.nn.forward(signal.to_array())
     }
 
-    pub fn get_rand_brain_output(&self) -> Array1<f32> {
-        let mut rng = thread_rng();
+    pub fn get_rand_brain_output(&self, rng: &mut impl Rng) -> Array1<f32> {
         Array1::from_shape_fn((4,), |_| rng.gen::<f32>())
     }
 }
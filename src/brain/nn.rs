@@ -2,21 +2,43 @@
 
 use std::fmt;
 
+use bevy::prelude::warn;
 use ndarray::prelude::*;
-use rand::{distributions::Uniform, prelude::Distribution};
+use rand::{distributions::Uniform, prelude::Distribution, Rng};
 use serde::{Serialize, Deserialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Activation {
     ReLU,
     Sigmoid,
+    Tanh,
+    LeakyReLU,
+    Identity,
+    Sin,
+    Gaussian,
 }
 
 impl Activation {
-    fn apply(&self, input: f32) -> f32 {
+    /// all activations, for choosing a random one in mutation
+    pub const ALL: [Activation; 7] = [
+        Activation::ReLU,
+        Activation::Sigmoid,
+        Activation::Tanh,
+        Activation::LeakyReLU,
+        Activation::Identity,
+        Activation::Sin,
+        Activation::Gaussian,
+    ];
+
+    pub fn apply(&self, input: f32) -> f32 {
         match self {
             Activation::ReLU => input.max(0.0),
             Activation::Sigmoid => 1.0 / (1.0 + (-input).exp()),
+            Activation::Tanh => input.tanh(),
+            Activation::LeakyReLU => input.max(0.01 * input),
+            Activation::Identity => input,
+            Activation::Sin => input.sin(),
+            Activation::Gaussian => (-input * input).exp(),
         }
     }
 }
@@ -25,31 +47,116 @@ impl Activation {
 pub struct BaseLayer {
     pub weights: Array2<f32>,
     pub bias: Array1<f32>,
+    /// Elman-style feedback from the last output of this layer,
+    /// `None` for feed-forward layers
+    #[serde(default)]
+    pub recurrent: Option<Array2<f32>>,
+    /// `None` uses the activation of `BaseNN`, like layers saved before
+    /// activations were chosen per layer
+    #[serde(default)]
+    pub activation: Option<Activation>,
+    /// last output of this layer, empty after spawn
+    #[serde(skip)]
+    state: Array1<f32>,
 }
 
 impl BaseLayer {
-    fn new_rand(nodes_in: usize, nodes_out: usize) -> BaseLayer {
+    fn new_rand(
+        nodes_in: usize,
+        nodes_out: usize,
+        recurrent: bool,
+        activation: Activation,
+        rng: &mut impl Rng,
+    ) -> BaseLayer {
         let weight_dist = Uniform::new(-1.0, 1.0);
         let bias_dist = Uniform::new(-1.0, 1.0);
 
-        let weights = Array::from_shape_fn((nodes_out, nodes_in), |_| {
-            weight_dist.sample(&mut rand::thread_rng())
+        let weights = Array::from_shape_fn((nodes_out, nodes_in), |_| weight_dist.sample(rng));
+        let bias = Array::from_shape_fn(nodes_out, |_| bias_dist.sample(rng));
+        let recurrent = recurrent.then(|| {
+            Array::from_shape_fn((nodes_out, nodes_out), |_| weight_dist.sample(rng))
         });
-        let bias = Array::from_shape_fn(nodes_out, |_| bias_dist.sample(&mut rand::thread_rng()));
 
-        BaseLayer { weights, bias }
+        BaseLayer {
+            weights,
+            bias,
+            recurrent,
+            activation: Some(activation),
+            state: Array1::zeros(0),
+        }
     }
 
     fn new_empty(nodes_in: usize, nodes_out: usize) -> BaseLayer {
         let weights = Array2::<f32>::zeros((nodes_out, nodes_in));
         let bias = Array1::<f32>::zeros(nodes_out);
-        BaseLayer { weights, bias }
+        BaseLayer {
+            weights,
+            bias,
+            recurrent: None,
+            activation: None,
+            state: Array1::zeros(0),
+        }
     }
 
-    fn forward(&self, input: &Array1<f32>, activation: &Activation) -> Array1<f32> {
+    /// `fallback` is the activation of `BaseNN`
+    fn forward(&mut self, input: &Array1<f32>, fallback: &Activation) -> Array1<f32> {
         assert_eq!(input.len(), self.weights.shape()[1]);
         let z = self.weights.dot(input) + &self.bias;
-        z.mapv(|x| activation.apply(x))
+        self.activate(z, fallback)
+    }
+
+    /// add recurrent feedback to the weighted sum `z` and apply the activation
+    fn activate(&mut self, mut z: Array1<f32>, fallback: &Activation) -> Array1<f32> {
+        if let Some(recurrent) = &self.recurrent {
+            // empty state means the first frame after spawn
+            if self.state.len() == z.len() {
+                z = z + recurrent.dot(&self.state);
+            }
+        }
+        let activation = self.activation.as_ref().unwrap_or(fallback);
+        let output = z.mapv(|x| activation.apply(x));
+        if self.recurrent.is_some() {
+            self.state = output.clone();
+        }
+        output
+    }
+
+    fn reset_state(&mut self) {
+        self.state = Array1::zeros(0);
+    }
+
+    /// layer passing its input through, cut or padded with zeros if sizes differ
+    fn new_identity(nodes_in: usize, nodes_out: usize) -> BaseLayer {
+        let mut layer = BaseLayer::new_empty(nodes_in, nodes_out);
+        for i in 0..nodes_in.min(nodes_out) {
+            layer.weights[[i, i]] = 1.0;
+        }
+        layer.activation = Some(Activation::Identity);
+        layer
+    }
+
+    /// keep the weights of existing nodes, weights of new nodes are zero
+    fn resize(&mut self, nodes_in: usize, nodes_out: usize) {
+        let (rows, cols) = self.weights.dim();
+        let (rows, cols) = (rows.min(nodes_out), cols.min(nodes_in));
+
+        let mut weights = Array2::<f32>::zeros((nodes_out, nodes_in));
+        weights
+            .slice_mut(s![..rows, ..cols])
+            .assign(&self.weights.slice(s![..rows, ..cols]));
+        let mut bias = Array1::<f32>::zeros(nodes_out);
+        bias.slice_mut(s![..rows]).assign(&self.bias.slice(s![..rows]));
+        if let Some(recurrent) = &mut self.recurrent {
+            let mut resized = Array2::<f32>::zeros((nodes_out, nodes_out));
+            resized
+                .slice_mut(s![..rows, ..rows])
+                .assign(&recurrent.slice(s![..rows, ..rows]));
+            *recurrent = resized;
+        }
+
+        self.weights = weights;
+        self.bias = bias;
+        self.reset_state();
     }
 }
 
@@ -57,9 +164,10 @@ impl fmt::Display for BaseLayer {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "Weights shape: {:?}, Bias shape: {:?}",
+            "Weights shape: {:?}, Bias shape: {:?}, Activation: {:?}",
             self.weights.dim(),
-            self.bias.dim()
+            self.bias.dim(),
+            self.activation
         )
     }
 }
@@ -67,19 +175,49 @@ impl fmt::Display for BaseLayer {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BaseNN {
     pub layers: Vec<BaseLayer>,
+    /// activation of layers without their own
     activation: Activation,
+    /// `[input, hidden..., output]`, empty in files saved before shapes were stored
+    #[serde(default)]
+    pub shape: Vec<usize>,
 }
 
 impl BaseNN {
-    pub fn new_rand(layer_sizes: Vec<usize>, activation: Activation) -> Self {
+    /// `activation` is used by hidden layers and `output_activation` by the output layer.
+    ///
+    /// `recurrent` gives all hidden layers hidden-state feedback,
+    /// the output layer is always feed-forward
+    pub fn new_rand(
+        layer_sizes: Vec<usize>,
+        activation: Activation,
+        output_activation: Activation,
+        recurrent: bool,
+        rng: &mut impl Rng,
+    ) -> Self {
         let mut layers = Vec::<BaseLayer>::new();
         if layer_sizes.len() <= 1 {
             panic!()
         }
         for i in 1..layer_sizes.len() {
-            layers.push(BaseLayer::new_rand(layer_sizes[i - 1], layer_sizes[i]));
+            let is_hidden = i < layer_sizes.len() - 1;
+            let layer_activation = if is_hidden {
+                activation.clone()
+            } else {
+                output_activation.clone()
+            };
+            layers.push(BaseLayer::new_rand(
+                layer_sizes[i - 1],
+                layer_sizes[i],
+                recurrent && is_hidden,
+                layer_activation,
+                rng,
+            ));
+        }
+        Self {
+            layers,
+            activation,
+            shape: layer_sizes,
         }
-        Self { layers, activation }
     }
 
     pub fn new_empty(layer_sizes: Vec<usize>, activation: Activation) -> Self {
@@ -90,16 +228,103 @@ impl BaseNN {
         for i in 1..layer_sizes.len() {
             layers.push(BaseLayer::new_empty(layer_sizes[i - 1], layer_sizes[i]));
         }
-        Self { layers, activation }
+        Self {
+            layers,
+            activation,
+            shape: layer_sizes,
+        }
     }
 
-    pub fn forward(&self, mut input: Array1<f32>) -> Array1<f32> {
+    pub fn forward(&mut self, mut input: Array1<f32>) -> Array1<f32> {
         // println!("{}",input.len());
-        for layer in &self.layers {
+        for layer in &mut self.layers {
             input = layer.forward(&input, &self.activation);
         }
         input
     }
+
+    /// clear the recurrent state, so that a respawned blob starts fresh
+    pub fn reset_state(&mut self) {
+        for layer in &mut self.layers {
+            layer.reset_state();
+        }
+    }
+
+    /// forward networks of the same shape together,
+    /// weights of each layer are stacked into one batched matmul.
+    ///
+    /// `nns` and `inputs` are paired
+    pub fn forward_batch(nns: &mut [&mut BaseNN], inputs: &[Array1<f32>]) -> Vec<Array1<f32>> {
+        if nns.is_empty() {
+            return Vec::new();
+        }
+        let views: Vec<_> = inputs.iter().map(|input| input.view()).collect();
+        // (batch, in)
+        let mut x = ndarray::stack(Axis(0), &views).unwrap();
+        for l in 0..nns[0].layers.len() {
+            let weights: Vec<_> = nns.iter().map(|nn| nn.layers[l].weights.view()).collect();
+            // (batch, out, in) * (batch, 1, in), summed to (batch, out)
+            let w = ndarray::stack(Axis(0), &weights).unwrap();
+            let z = (&w * &x.view().insert_axis(Axis(1))).sum_axis(Axis(2));
+
+            let mut outputs = Vec::<Array1<f32>>::new();
+            for (nn, z) in nns.iter_mut().zip(z.outer_iter()) {
+                let layer = &mut nn.layers[l];
+                let z = &z + &layer.bias;
+                outputs.push(layer.activate(z, &nn.activation));
+            }
+            let views: Vec<_> = outputs.iter().map(|output| output.view()).collect();
+            x = ndarray::stack(Axis(0), &views).unwrap();
+        }
+        x.outer_iter().map(|row| row.to_owned()).collect()
+    }
+
+    /// `[input, hidden..., output]` read from the weights
+    pub fn layer_shape(&self) -> Vec<usize> {
+        let mut shape = vec![self.layers[0].weights.ncols()];
+        shape.extend(self.layers.iter().map(|layer| layer.weights.nrows()));
+        shape
+    }
+
+    /// check the network against `shape`, and migrate it if they differ.
+    ///
+    /// Weights of kept nodes stay, new inputs and nodes start with zero weights.
+    /// Extra hidden layers are dropped from the end,
+    /// missing ones are added before the output layer and pass their input through.
+    ///
+    /// Return `true` if the network was migrated
+    pub fn migrate(&mut self, shape: &[usize]) -> bool {
+        assert!(shape.len() >= 2);
+        let current = self.layer_shape();
+        if !self.shape.is_empty() && self.shape != current {
+            warn!("stored shape {:?} differs from layers {:?}", self.shape, current);
+        }
+        self.shape = current;
+        if self.shape == shape {
+            return false;
+        }
+
+        let hidden = shape.len() - 2;
+        let mut output = self.layers.pop();
+        let mut old_hidden = std::mem::take(&mut self.layers).into_iter();
+        for i in 0..=hidden {
+            let (nodes_in, nodes_out) = (shape[i], shape[i + 1]);
+            let layer = if i == hidden {
+                output.take()
+            } else {
+                old_hidden.next()
+            };
+            self.layers.push(match layer {
+                Some(mut layer) => {
+                    layer.resize(nodes_in, nodes_out);
+                    layer
+                }
+                None => BaseLayer::new_identity(nodes_in, nodes_out),
+            });
+        }
+        self.shape = shape.to_vec();
+        true
+    }
 }
 
 impl fmt::Display for BaseNN {
@@ -117,3 +342,110 @@ impl fmt::Display for BaseNN {
         )
     }
 }
+
+#[cfg(test)]
+mod nn_test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test_recurrent_state() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut nn = BaseNN::new_rand(
+            vec![3, 4, 2],
+            Activation::Sigmoid,
+            Activation::Sigmoid,
+            true,
+            &mut rng,
+        );
+        assert!(nn.layers[0].recurrent.is_some());
+        assert!(nn.layers[1].recurrent.is_none());
+
+        let input = Array1::from_vec(vec![0.5, -0.5, 1.0]);
+        let first = nn.forward(input.clone());
+        let second = nn.forward(input.clone());
+        assert_ne!(first, second);
+
+        nn.reset_state();
+        assert_eq!(nn.forward(input), first);
+    }
+
+    #[test]
+    fn test_forward_batch() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut nns: Vec<BaseNN> = (0..3)
+            .map(|_| {
+                BaseNN::new_rand(
+                    vec![3, 4, 2],
+                    Activation::Tanh,
+                    Activation::Sigmoid,
+                    true,
+                    &mut rng,
+                )
+            })
+            .collect();
+        let mut single = nns.clone();
+        let inputs: Vec<Array1<f32>> = (0..3)
+            .map(|i| Array1::from_vec(vec![i as f32, 0.5, -1.0]))
+            .collect();
+
+        // twice, so that the recurrent state is used
+        for _ in 0..2 {
+            let mut refs: Vec<&mut BaseNN> = nns.iter_mut().collect();
+            let batched = BaseNN::forward_batch(&mut refs, &inputs);
+            for ((nn, input), output) in single.iter_mut().zip(inputs.iter()).zip(batched) {
+                let expected = nn.forward(input.clone());
+                assert!((expected - output).iter().all(|x| x.abs() < 1e-5));
+            }
+        }
+    }
+
+    #[test]
+    fn test_migrate() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut nn = BaseNN::new_rand(
+            vec![3, 4, 2],
+            Activation::Sigmoid,
+            Activation::Tanh,
+            false,
+            &mut rng,
+        );
+        let before = nn.forward(Array1::from_vec(vec![0.5, -0.5, 1.0]));
+
+        assert!(!nn.migrate(&[3, 4, 2]));
+        // a new input and a new hidden layer don't change the output
+        assert!(nn.migrate(&[4, 4, 4, 2]));
+        assert_eq!(nn.shape, vec![4, 4, 4, 2]);
+        let after = nn.forward(Array1::from_vec(vec![0.5, -0.5, 1.0, 3.0]));
+        assert!((before - after).iter().all(|x| x.abs() < 1e-6));
+
+        assert!(nn.migrate(&[2, 3, 1]));
+        assert_eq!(nn.layer_shape(), vec![2, 3, 1]);
+        assert_eq!(nn.forward(Array1::zeros(2)).len(), 1);
+    }
+
+    #[test]
+    fn test_layer_activation() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut nn = BaseNN::new_rand(
+            vec![3, 4, 2],
+            Activation::ReLU,
+            Activation::Tanh,
+            false,
+            &mut rng,
+        );
+        assert_eq!(nn.layers[0].activation, Some(Activation::ReLU));
+        assert_eq!(nn.layers[1].activation, Some(Activation::Tanh));
+        let output = nn.forward(Array1::from_vec(vec![5.0, -5.0, 5.0]));
+        assert!(output.iter().all(|x| x.abs() < 1.0));
+
+        // layers without activation use the one of the network
+        nn.layers[1].activation = None;
+        nn.activation = Activation::Identity;
+        let layer = &mut nn.layers[1];
+        let input = Array1::from_vec(vec![1.0, 0.0, 0.0, 0.0]);
+        let expected = layer.weights.column(0).to_owned() + &layer.bias;
+        assert_eq!(layer.forward(&input, &Activation::Identity), expected);
+    }
+}
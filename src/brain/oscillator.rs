@@ -0,0 +1,102 @@
+//! evolvable sine oscillator, a rhythm source for blocks.
+//!
+//! The phase of a block's oscillator is pulled towards its parent's phase plus an evolved offset,
+//! so limbs along a chain can keep a travelling wave.
+
+use std::f32::consts::PI;
+
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Oscillator {
+    /// cycles per second
+    pub frequency: f32,
+    /// phase offset to the parent's oscillator, or the initial phase without parent
+    pub phase: f32,
+    pub amplitude: f32,
+    /// how strong the phase is pulled towards the parent's
+    pub coupling: f32,
+    /// current phase, `None` after spawn
+    #[serde(skip)]
+    state: Option<f32>,
+}
+
+impl Oscillator {
+    pub fn new_rand(rng: &mut impl Rng) -> Self {
+        Self {
+            frequency: rng.gen_range(0.2..2.0),
+            phase: rng.gen_range(-PI..PI),
+            amplitude: rng.gen_range(0.0..1.0),
+            coupling: rng.gen_range(0.0..2.0),
+            state: None,
+        }
+    }
+
+    /// current phase, starts at the offset to parent's phase
+    pub fn current_phase(&self, parent_phase: Option<f32>) -> f32 {
+        self.state
+            .unwrap_or(parent_phase.map_or(self.phase, |p| p + self.phase))
+    }
+
+    /// advance the phase by `dt` seconds, coupling to `parent_phase` if there is one
+    pub fn step(&mut self, dt: f32, parent_phase: Option<f32>) {
+        let theta = self.current_phase(parent_phase);
+        let mut d_theta = 2.0 * PI * self.frequency;
+        if let Some(parent_phase) = parent_phase {
+            d_theta += self.coupling * (parent_phase + self.phase - theta).sin();
+        }
+        self.state = Some((theta + d_theta * dt).rem_euclid(2.0 * PI));
+    }
+
+    pub fn output(&self) -> f32 {
+        self.amplitude * self.current_phase(None).sin()
+    }
+
+    pub fn reset_state(&mut self) {
+        self.state = None;
+    }
+
+    /// keep parameters in a meaningful range after mutation
+    pub fn clamp(&mut self) {
+        self.frequency = self.frequency.max(0.0);
+        self.amplitude = self.amplitude.max(0.0);
+        self.coupling = self.coupling.max(0.0);
+        self.phase = (self.phase + PI).rem_euclid(2.0 * PI) - PI;
+    }
+}
+
+#[cfg(test)]
+mod oscillator_test {
+    use super::*;
+
+    #[test]
+    fn test_phase_locking() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut parent = Oscillator::new_rand(&mut rng);
+        let mut child = Oscillator {
+            frequency: parent.frequency * 1.1,
+            phase: 0.5,
+            amplitude: 1.0,
+            coupling: 5.0,
+            state: Some(3.0),
+        };
+        let dt = 1.0 / 60.0;
+        for _ in 0..2000 {
+            let parent_phase = parent.current_phase(None);
+            child.step(dt, Some(parent_phase));
+            parent.step(dt, None);
+        }
+        // the child keeps a fixed phase difference to the parent
+        let diff = |child: &Oscillator, parent: &Oscillator| {
+            (child.current_phase(None) - parent.current_phase(None)).rem_euclid(2.0 * PI)
+        };
+        let before = diff(&child, &parent);
+        for _ in 0..60 {
+            let parent_phase = parent.current_phase(None);
+            child.step(dt, Some(parent_phase));
+            parent.step(dt, None);
+        }
+        assert!((diff(&child, &parent) - before).abs() < 0.05);
+    }
+}
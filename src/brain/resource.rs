@@ -1,62 +1,116 @@
 //! implementation about `BevyBlockNeurons`, which is a wrapper around neural network to make it into a bevy resource.
 
-use std::f32::consts::PI;
+use std::{collections::HashMap, f32::consts::PI};
 
 use bevy::prelude::*;
 use ndarray::prelude::*;
 use rand::prelude::*;
+use rayon::prelude::*;
 
 use crate::{
     brain::signal::InwardNNInputSignalUnit,
+    config::config,
     consts::{MOTOR_MAX_TARGET_V, OUTWARD_NN_PARENT_INPUT_LEN},
 };
 
 use super::{
-    neuron::GenericNN,
+    activity::ActivityRecorder,
+    neat::NeatNN,
+    neuron::{AnyNN, BlockNN, GenericNN},
+    nn::BaseNN,
+    oscillator::Oscillator,
     signal::{BrainSignalUnit, SignalHandler},
 };
 
 const DL: usize = OUTWARD_NN_PARENT_INPUT_LEN;
+/// min units per parallel job, smaller layers are not worth splitting
+const PAR_MIN_LEN: usize = 8;
 
-// TODO: add random generator and oscillator
+// TODO: add random generator
 /// Bevy resource, which make sure the neurons can be accessed
 /// and modified from bevy side
 #[derive(Resource, Debug)]
 pub struct BevyBlockNeurons {
     pub nnvec: Vec<GenericNN>,
+    /// inputs and outputs of blocks in recent frames, for debugging
+    pub activity: ActivityRecorder,
 }
 
 impl Default for BevyBlockNeurons {
     fn default() -> Self {
         let nnv = Vec::<GenericNN>::new();
-        Self { nnvec: nnv }
+        let debug = &config().debug;
+        Self {
+            nnvec: nnv,
+            activity: ActivityRecorder::new(debug.record_activity, debug.activity_window as u128),
+        }
+    }
+}
+
+/// arrays of a frame for `ActivityRecorder`, index is nn_id
+struct Trace {
+    inward_input: Vec<Array1<f32>>,
+    inward_output: Vec<Array1<f32>>,
+}
+
+impl Trace {
+    fn new(len: usize) -> Self {
+        Self {
+            inward_input: vec![Array1::zeros(0); len],
+            inward_output: vec![Array1::zeros(0); len],
+        }
     }
 }
 
 impl BevyBlockNeurons {
-    // TODO: parallel, gpu
+    // TODO: gpu
     /// start neuron computing and return outputs
+    ///
+    /// Each depth layer is evaluated in parallel across blobs.
+    ///
+    /// If the activity recorder is enabled, the arrays wait in it for `ActivityRecorder::commit`
     pub fn get_outputs(&mut self, mut signal_handler: SignalHandler) -> Vec<(Entity, f32, f32)> {
         // store output value for joint motors
         let mut outputs: Vec<(Entity, f32, f32)> = Vec::new();
         // store internal outward_nn's outputs, index is nn_id
         let mut outward_passes = vec![Array1::<f32>::zeros(DL); self.nnvec.len()];
 
+        step_oscillators(
+            &signal_handler.inward_signal_vec,
+            &mut self.nnvec,
+            config().physics.rapier_dt,
+        );
+
         // generate grouped signal
         let (mut grouped_signal, mut brain_signal) = signal_handler.get_sig_mut();
+        let batched = config().nn.batched;
+        let mut trace = self.activity.is_enabled().then(|| Trace::new(self.nnvec.len()));
 
         // println!("grouped signal {:#?}",grouped_signal);
         // println!("brain signal {:#?}",brain_signal);
         // passing through all inward layers
         for idx in (1..grouped_signal.len()).rev() {
-            inward_bulk_pass(&mut grouped_signal, &mut self.nnvec, idx)
+            inward_bulk_pass(
+                &mut grouped_signal,
+                &mut self.nnvec,
+                idx,
+                batched,
+                trace.as_mut(),
+            )
         }
 
         // passing to brain
-        brain_pass(&mut brain_signal, &grouped_signal[0], &mut self.nnvec);
+        brain_pass(
+            &mut brain_signal,
+            &grouped_signal[0],
+            &mut self.nnvec,
+            batched,
+            trace.as_mut(),
+        );
         // println!("{:#?}",brain_signal[0].signal);
-        brain_forward(&brain_signal, &mut self.nnvec, &mut outward_passes);
+        brain_forward(&brain_signal, &mut self.nnvec, &mut outward_passes, batched);
 
+        let mut outward_outputs = Vec::<Array1<f32>>::new();
         for idx in 0..grouped_signal.len() {
             outward_bulk_pass(
                 &mut grouped_signal,
@@ -64,14 +118,29 @@ impl BevyBlockNeurons {
                 idx,
                 &mut outputs,
                 &mut outward_passes,
+                batched,
+                trace.is_some().then_some(&mut outward_outputs),
             )
         }
 
+        if let Some(trace) = trace {
+            // outputs are pushed in the same order as outward_outputs
+            let units = grouped_signal.iter().flatten();
+            for ((unit, output), outward) in units.zip(outputs.iter()).zip(outward_outputs) {
+                self.activity.push_pending(
+                    output.0,
+                    trace.inward_input[unit.nn_id].to_vec(),
+                    trace.inward_output[unit.nn_id].to_vec(),
+                    outward.to_vec(),
+                    (output.1, output.2),
+                );
+            }
+        }
+
         outputs
     }
 
-    pub fn get_rand_outputs(&self, signal_handler: SignalHandler) -> Vec<[f32; 2]> {
-        let mut rng = thread_rng();
+    pub fn get_rand_outputs(&self, signal_handler: SignalHandler, rng: &mut impl Rng) -> Vec<[f32; 2]> {
         let len = signal_handler.inward_len();
         vec![
             [
@@ -88,6 +157,138 @@ impl BevyBlockNeurons {
     }
 }
 
+/// advance oscillators of all blocks by one frame, blocks couple to their parent's phase
+fn step_oscillators(units: &[InwardNNInputSignalUnit], nnvec: &mut [GenericNN], dt: f32) {
+    fn oscillator_of(nn: &GenericNN) -> Option<&Oscillator> {
+        match nn {
+            GenericNN::BLOCKNN(nn) => nn.oscillator.as_ref(),
+            GenericNN::BRAINNN(_) => None,
+        }
+    }
+    // read all parent phases before any block steps
+    let parent_phases: Vec<Option<f32>> = units
+        .iter()
+        .map(|unit| {
+            let parent = oscillator_of(&nnvec[unit.parent_nn_id])?;
+            // the parent's parent phase is unknown here, it only matters on the first frame
+            Some(parent.current_phase(None))
+        })
+        .collect();
+
+    for (unit, parent_phase) in units.iter().zip(parent_phases) {
+        if let GenericNN::BLOCKNN(nn) = &mut nnvec[unit.nn_id] {
+            if let Some(oscillator) = &mut nn.oscillator {
+                oscillator.step(dt, parent_phase);
+            }
+        }
+    }
+}
+
+/// mutable references to the NNs of `ids`, in the same order
+///
+/// ids must be unique
+fn nns_of<'a>(
+    nnvec: &'a mut [GenericNN],
+    ids: impl Iterator<Item = usize>,
+) -> Vec<&'a mut GenericNN> {
+    let mut slots: Vec<Option<&mut GenericNN>> = nnvec.iter_mut().map(Some).collect();
+    ids.map(|id| slots[id].take().expect("nn id appears twice in a layer"))
+        .collect()
+}
+
+fn as_block_nn(nn: &mut GenericNN) -> &mut BlockNN {
+    match nn {
+        GenericNN::BLOCKNN(nn) => nn,
+        GenericNN::BRAINNN(_) => panic!("nn is expected to be BLOCKNN, but found BRAINNN"),
+    }
+}
+
+/// index map from nn_id to the position in `ids`, replaces linear search of parents
+fn index_map(ids: impl Iterator<Item = usize>, len: usize) -> Vec<Option<usize>> {
+    let mut map = vec![None; len];
+    for (idx, id) in ids.enumerate() {
+        map[id] = Some(idx);
+    }
+    map
+}
+
+/// forward all networks in parallel, paired with `inputs`.
+///
+/// If `batched` is set, fixed-layer networks of the same shape go through
+/// `BaseNN::forward_batch` together
+fn forward_all(nns: Vec<&mut AnyNN>, inputs: Vec<Array1<f32>>, batched: bool) -> Vec<Array1<f32>> {
+    if !batched {
+        return nns
+            .into_par_iter()
+            .zip(inputs)
+            .with_min_len(PAR_MIN_LEN)
+            .map(|(nn, input)| nn.forward(input))
+            .collect();
+    }
+
+    // (position, nn, input) grouped by shape, NEAT networks are not batched
+    let mut groups = HashMap::<Vec<usize>, Vec<(usize, &mut BaseNN, Array1<f32>)>>::new();
+    let mut singles = Vec::<(usize, &mut NeatNN, Array1<f32>)>::new();
+    let len = nns.len();
+    for (pos, (nn, input)) in nns.into_iter().zip(inputs).enumerate() {
+        match nn {
+            AnyNN::Base(nn) => groups.entry(nn.layer_shape()).or_default().push((pos, nn, input)),
+            AnyNN::Neat(nn) => singles.push((pos, nn, input)),
+        }
+    }
+
+    let mut results: Vec<(usize, Array1<f32>)> = groups
+        .into_par_iter()
+        .flat_map_iter(|(_, group)| {
+            let (positions, (mut nns, inputs)): (Vec<_>, (Vec<_>, Vec<_>)) = group
+                .into_iter()
+                .map(|(pos, nn, input)| (pos, (nn, input)))
+                .unzip();
+            positions
+                .into_iter()
+                .zip(BaseNN::forward_batch(&mut nns, &inputs))
+        })
+        .chain(
+            singles
+                .into_par_iter()
+                .map(|(pos, nn, input)| (pos, nn.forward(input))),
+        )
+        .collect();
+    results.sort_by_key(|(pos, _)| *pos);
+    assert_eq!(results.len(), len);
+    results.into_iter().map(|(_, output)| output).collect()
+}
+
+/// inward outputs of all units in a layer, evaluated in parallel
+fn inward_outputs(
+    layer: &[&mut InwardNNInputSignalUnit],
+    nnvec: &mut [GenericNN],
+    batched: bool,
+    trace: Option<&mut Trace>,
+) -> Vec<Array1<f32>> {
+    let mut blocks: Vec<&mut BlockNN> = nns_of(nnvec, layer.iter().map(|u| u.nn_id))
+        .into_iter()
+        .map(as_block_nn)
+        .collect();
+    let inputs: Vec<Array1<f32>> = blocks
+        .par_iter_mut()
+        .zip(layer.par_iter())
+        .with_min_len(PAR_MIN_LEN)
+        .map(|(nn, unit)| nn.inward_input(&unit.signal))
+        .collect();
+    let nns = blocks.into_iter().map(|nn| &mut nn.inward_nn.nn).collect();
+    let Some(trace) = trace else {
+        return forward_all(nns, inputs, batched);
+    };
+
+    let outputs = forward_all(nns, inputs.clone(), batched);
+    for ((unit, input), output) in layer.iter().zip(inputs).zip(outputs.iter()) {
+        trace.inward_input[unit.nn_id] = input;
+        trace.inward_output[unit.nn_id] = output.clone();
+    }
+    outputs
+}
+
 /// Pass the signal from the leaf to the root layer by layer
 ///
 /// bulk_idx can not be 0
@@ -95,6 +296,8 @@ fn inward_bulk_pass(
     grouped_signal: &mut Vec<Vec<&mut InwardNNInputSignalUnit>>,
     nnvec: &mut Vec<GenericNN>,
     bulk_idx: usize,
+    batched: bool,
+    trace: Option<&mut Trace>,
 ) {
     if bulk_idx == 0 {
         panic!()
@@ -103,23 +306,15 @@ fn inward_bulk_pass(
     // aviod multiple borrow here
     let (left, right) = grouped_signal.split_at_mut(bulk_idx);
     let passed_layer: &mut Vec<&mut InwardNNInputSignalUnit> = &mut left[bulk_idx - 1];
-    let current_layer: &mut Vec<&mut InwardNNInputSignalUnit> = &mut right[0];
+    let current_layer: &Vec<&mut InwardNNInputSignalUnit> = &right[0];
 
-    // TODO: parallel this for loop
-    for unit in current_layer {
-        if let GenericNN::BLOCKNN(nn) = &mut nnvec[unit.nn_id] {
-            passed_layer
-                .iter_mut()
-                .find(|u| u.nn_id == unit.parent_nn_id)
-                .unwrap()
-                .get_signal_mut()
-                .push_child_signal(nn.get_inward_output(&unit.signal), unit.anchor_pos);
-        } else {
-            panic!(
-                "nn with id {} is expected to be BLOCKNN, but found BRAINNN",
-                unit.nn_id
-            )
-        }
+    let outputs = inward_outputs(current_layer, nnvec, batched, trace);
+    let parents = index_map(passed_layer.iter().map(|u| u.nn_id), nnvec.len());
+    for (unit, output) in current_layer.iter().zip(outputs) {
+        let parent = parents[unit.parent_nn_id].unwrap();
+        passed_layer[parent]
+            .get_signal_mut()
+            .push_child_signal(output, unit.anchor_pos);
     }
 }
 
@@ -128,21 +323,16 @@ fn brain_pass(
     brain_signal: &mut Vec<&mut BrainSignalUnit>,
     current_layer: &Vec<&mut InwardNNInputSignalUnit>,
     nnvec: &mut Vec<GenericNN>,
+    batched: bool,
+    trace: Option<&mut Trace>,
 ) {
-    for unit in current_layer {
-        if let GenericNN::BLOCKNN(nn) = &mut nnvec[unit.nn_id] {
-            brain_signal
-                .iter_mut()
-                .find(|u: &&mut &mut BrainSignalUnit| u.nn_id == unit.parent_nn_id)
-                .unwrap()
-                .get_signal_mut()
-                .push_child_signal(nn.get_inward_output(&unit.signal), unit.anchor_pos);
-        } else {
-            panic!(
-                "nn with id {} is expected to be BLOCKNN, but found BRAINNN",
-                unit.nn_id
-            )
-        }
+    let outputs = inward_outputs(current_layer, nnvec, batched, trace);
+    let parents = index_map(brain_signal.iter().map(|u| u.nn_id), nnvec.len());
+    for (unit, output) in current_layer.iter().zip(outputs) {
+        let parent = parents[unit.parent_nn_id].unwrap();
+        brain_signal[parent]
+            .get_signal_mut()
+            .push_child_signal(output, unit.anchor_pos);
     }
 }
 
@@ -151,15 +341,19 @@ fn brain_forward(
     brain_signal: &Vec<&mut BrainSignalUnit>,
     nnvec: &mut Vec<GenericNN>,
     outward_passes: &mut Vec<Array1<f32>>,
+    batched: bool,
 ) {
-    for signal in brain_signal {
-        if let Some(GenericNN::BRAINNN(brain)) = nnvec.get(signal.nn_id) {
-            // println!("{:#?}",signal.signal);
-            // store forward result
-            outward_passes[signal.nn_id] = brain.forward(&signal.signal);
-        } else {
-            panic!()
-        }
+    let nns = nns_of(nnvec, brain_signal.iter().map(|u| u.nn_id))
+        .into_iter()
+        .map(|nn| match nn {
+            GenericNN::BRAINNN(brain) => &mut brain.nn,
+            GenericNN::BLOCKNN(_) => panic!("nn is expected to be BRAINNN, but found BLOCKNN"),
+        })
+        .collect();
+    let inputs = brain_signal.iter().map(|u| u.signal.to_array()).collect();
+    // store forward result
+    for (unit, output) in brain_signal.iter().zip(forward_all(nns, inputs, batched)) {
+        outward_passes[unit.nn_id] = output;
     }
 }
 
@@ -169,19 +363,33 @@ fn outward_bulk_pass(
     bulk_idx: usize,
     outputs: &mut Vec<(Entity, f32, f32)>,
     outward_passes: &mut Vec<Array1<f32>>,
+    batched: bool,
+    trace: Option<&mut Vec<Array1<f32>>>,
 ) {
     let current_layer = &grouped_signal[bulk_idx];
 
-    // TODO: parallel this loop
-    for unit in current_layer {
-        if let GenericNN::BLOCKNN(nn) = &mut nnvec[unit.nn_id] {
-            // get result from parent and write output back
-            let a = nn.get_outward_output(&outward_passes[unit.parent_nn_id]);
-            outward_passes[unit.nn_id] = a.slice(s![..DL]).map(|x| *x).clone();
-            // push result
-            outputs.push((unit.entity_id, a[DL], a[DL + 1]));
-        } else {
-            panic!()
-        }
+    let mut blocks: Vec<&mut BlockNN> = nns_of(nnvec, current_layer.iter().map(|u| u.nn_id))
+        .into_iter()
+        .map(as_block_nn)
+        .collect();
+    // get result from parent
+    let passes: &Vec<Array1<f32>> = outward_passes;
+    let inputs: Vec<Array1<f32>> = blocks
+        .par_iter_mut()
+        .zip(current_layer.par_iter())
+        .with_min_len(PAR_MIN_LEN)
+        .map(|(nn, unit)| nn.outward_input(&passes[unit.parent_nn_id]))
+        .collect();
+    let nns = blocks.into_iter().map(|nn| &mut nn.outward_nn.nn).collect();
+
+    // write output back
+    let results = forward_all(nns, inputs, batched);
+    for (unit, a) in current_layer.iter().zip(results.iter()) {
+        outward_passes[unit.nn_id] = a.slice(s![..DL]).to_owned();
+        // push result
+        outputs.push((unit.entity_id, a[DL], a[DL + 1]));
+    }
+    if let Some(trace) = trace {
+        trace.extend(results);
     }
 }
@@ -5,6 +5,7 @@ use std::fmt;
 use crate::{
     blob::block::{BlockDepth, ParentAnchor},
     consts::*,
+    contorl::touch::Touch,
 };
 use bevy::prelude::Entity;
 use itertools::Itertools;
@@ -152,6 +153,9 @@ pub struct InwardNNInputSignal {
     collision_with_other_blob: bool,
     collision_vect: [f32; 2],
     collision_mag: f32,
+    /// in [0, 1], see `Touch::count_input`
+    contact_count: f32,
+    contact_faces: [bool; 4],
 
     // joint signal
     cur_motor_pos: f32,
@@ -163,6 +167,9 @@ pub struct InwardNNInputSignal {
     ///
     /// Order of children inputs depends on children's parent_anchor.
     children_input: Array2<f32>,
+
+    /// raycast vision, empty if disabled
+    vision: Vec<f32>,
 }
 
 impl Default for InwardNNInputSignal {
@@ -172,22 +179,27 @@ impl Default for InwardNNInputSignal {
             collision_with_other_blob: false,
             collision_vect: [0.0, 0.0],
             collision_mag: 0.0,
+            contact_count: 0.0,
+            contact_faces: [false; 4],
             cur_motor_pos: 0.0,
             cur_motor_v: 0.0,
             joint_ang_pos: 0.0,
             joint_ang_v: 0.0,
             children_input: Array2::<f32>::zeros((4, CL)),
+            vision: Vec::new(),
         }
     }
 }
 
 impl InwardNNInputSignal {
-    pub fn with_cf_signal(mut self, signal: Option<(bool, bool, [f32; 2], f32)>) -> Self {
-        if let Some((wall, blob, vect, mag)) = signal {
-            self.collision_with_wall = wall;
-            self.collision_with_other_blob = blob;
-            self.collision_vect = vect;
-            self.collision_mag = mag;
+    pub fn with_touch(mut self, touch: Option<&Touch>) -> Self {
+        if let Some(touch) = touch {
+            self.collision_with_wall = touch.wall;
+            self.collision_with_other_blob = touch.other_blob;
+            self.collision_vect = touch.force_input();
+            self.collision_mag = touch.magnitude_input();
+            self.contact_count = touch.count_input();
+            self.contact_faces = touch.faces;
         }
         self
     }
@@ -201,6 +213,11 @@ impl InwardNNInputSignal {
         self
     }
 
+    pub fn with_vision(mut self, vision: Vec<f32>) -> Self {
+        self.vision = vision;
+        self
+    }
+
     pub fn push_child_signal(&mut self, signal: Array1<f32>, anchor: usize) {
         // anchor must in 0..=3
         match anchor {
@@ -232,12 +249,19 @@ impl InwardNNInputSignal {
             .chain(std::iter::once(self.cur_motor_v))
             .chain(std::iter::once(self.joint_ang_pos))
             .chain(std::iter::once(self.joint_ang_v))
-            .chain(children_data);
+            .chain(children_data)
+            .chain(touch_data(self.contact_count, self.contact_faces))
+            .chain(self.vision.iter().cloned());
 
         Array1::from_iter(all_data)
     }
 }
 
+/// contact count and face flags, `TOUCH_INPUT_LEN` values
+fn touch_data(count: f32, faces: [bool; 4]) -> impl Iterator<Item = f32> {
+    std::iter::once(count).chain(faces.into_iter().map(|face| face as u8 as f32))
+}
+
 /// Input singal for single outward `BlockNeuron`
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OutwardNNInputSignal {
@@ -296,6 +320,9 @@ pub struct BrainSignal {
     collision_with_other_blob: bool,
     collision_vect: [f32; 2],
     collision_mag: f32,
+    /// in [0, 1], see `Touch::count_input`
+    contact_count: f32,
+    contact_faces: [bool; 4],
 
     /// input singal from children neurons.
     /// Shape is (4,CL)
@@ -303,6 +330,23 @@ pub struct BrainSignal {
 
     blob_mass_center: [f32; 2],
     blob_speed: [f32; 2],
+
+    // orientation signal, in body frame
+    /// sin and cos of the absolute rotation
+    rotation: [f32; 2],
+    angular_velocity: f32,
+    /// unit vector, zero without gravity
+    gravity: [f32; 2],
+    /// height above the ground in walk mode, zero otherwise
+    height: f32,
+
+    /// smell intensity and gradient in body frame, `None` unless foraging
+    smell: Option<[f32; 3]>,
+    /// energy left as a ratio of `EnergyConfig::initial` in [0, 1], `None` if disabled
+    energy: Option<f32>,
+
+    /// raycast vision, empty if disabled
+    vision: Vec<f32>,
 }
 
 impl Default for BrainSignal {
@@ -312,20 +356,31 @@ impl Default for BrainSignal {
             collision_with_other_blob: false,
             collision_vect: [0.0, 0.0],
             collision_mag: 0.0,
+            contact_count: 0.0,
+            contact_faces: [false; 4],
             children_input: Array2::<f32>::zeros((4, CL)),
             blob_mass_center: [0.0, 0.0],
             blob_speed: [0.0, 0.0],
+            rotation: [0.0, 1.0],
+            angular_velocity: 0.0,
+            gravity: [0.0, 0.0],
+            height: 0.0,
+            smell: None,
+            energy: None,
+            vision: Vec::new(),
         }
     }
 }
 
 impl BrainSignal {
-    pub fn with_cf_signal(mut self, signal: Option<(bool, bool, [f32; 2], f32)>) -> Self {
-        if let Some((wall, blob, vect, mag)) = signal {
-            self.collision_with_wall = wall;
-            self.collision_with_other_blob = blob;
-            self.collision_vect = vect;
-            self.collision_mag = mag;
+    pub fn with_touch(mut self, touch: Option<&Touch>) -> Self {
+        if let Some(touch) = touch {
+            self.collision_with_wall = touch.wall;
+            self.collision_with_other_blob = touch.other_blob;
+            self.collision_vect = touch.force_input();
+            self.collision_mag = touch.magnitude_input();
+            self.contact_count = touch.count_input();
+            self.contact_faces = touch.faces;
         }
         self
     }
@@ -336,6 +391,42 @@ impl BrainSignal {
         self
     }
 
+    /// `rotation` in radians, `gravity` in world frame
+    pub fn with_orientation(
+        mut self,
+        rotation: f32,
+        angular_velocity: f32,
+        gravity: [f32; 2],
+        height: f32,
+    ) -> Self {
+        let (sin, cos) = rotation.sin_cos();
+        self.rotation = [sin, cos];
+        self.angular_velocity = angular_velocity;
+        // rotate gravity into body frame
+        let [gx, gy] = gravity;
+        let norm = (gx * gx + gy * gy).sqrt();
+        if norm > 0.0 {
+            self.gravity = [(gx * cos + gy * sin) / norm, (gy * cos - gx * sin) / norm];
+        }
+        self.height = height;
+        self
+    }
+
+    pub fn with_smell(mut self, smell: Option<[f32; 3]>) -> Self {
+        self.smell = smell;
+        self
+    }
+
+    pub fn with_energy(mut self, energy: Option<f32>) -> Self {
+        self.energy = energy;
+        self
+    }
+
+    pub fn with_vision(mut self, vision: Vec<f32>) -> Self {
+        self.vision = vision;
+        self
+    }
+
     pub fn push_child_signal(&mut self, signal: Array1<f32>, anchor: usize) {
         // anchor must in 0..=3
         match anchor {
@@ -367,7 +458,15 @@ impl BrainSignal {
             .chain(std::iter::once(self.collision_mag))
             .chain(children_data)
             .chain(mass_center_data)
-            .chain(speed_data);
+            .chain(speed_data)
+            .chain(touch_data(self.contact_count, self.contact_faces))
+            .chain(self.rotation)
+            .chain(std::iter::once(self.angular_velocity))
+            .chain(self.gravity)
+            .chain(std::iter::once(self.height))
+            .chain(self.smell.into_iter().flatten())
+            .chain(self.energy)
+            .chain(self.vision.iter().cloned());
 
         Array1::from_iter(all_data)
     }
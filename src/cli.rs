@@ -0,0 +1,271 @@
+//! command-line interface, so batch scripts can launch runs without editing source
+//!
+//! ```text
+//! evosim train --mode walk --population 60 --generations 500 --seed 42 \
+//!     --config run.toml --load export/x.json --out runs/exp1
+//! evosim train --headless --generations 500 --out runs/exp2
+//! evosim lineage runs/exp1/lineage.jsonl --out runs/exp1/lineage.dot
+//! ```
+//!
+//! Arguments are applied on top of the config file, the result is the `SimConfig` of the run.
+
+use std::{error::Error, fs, path::Path};
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::{
+    config::{Profile, SimConfig, TrainingMode},
+    consts::DEFAULT_CONFIG_PATH,
+    io::{
+        import::read_export_file,
+        lineage::{read_records, to_dot},
+    },
+};
+
+/// EvoSim, evolving virtual creatures
+///
+/// start the windowed simulation with `./evosim.toml` (or default values) if no command is given
+#[derive(Parser, Debug)]
+#[command(name = "evosim", version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// train blobs to move
+    Train(TrainArgs),
+    /// simple rand demo (mainly for mutation demo)
+    Demo(CommonArgs),
+    /// export the ancestry tree of a lineage file in DOT format, without running the simulation
+    Lineage(LineageArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct CommonArgs {
+    /// config file (TOML or JSON)
+    #[arg(long)]
+    pub config: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct TrainArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+    /// training mode
+    #[arg(long, value_enum)]
+    pub mode: Option<TrainingMode>,
+    /// population for each training iteration
+    #[arg(long)]
+    pub population: Option<usize>,
+    /// exit after this many generations
+    #[arg(long)]
+    pub generations: Option<usize>,
+    /// seed of the random number generator
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// start training from an exported file or checkpoint
+    #[arg(long)]
+    pub load: Option<String>,
+    /// output folder for checkpoints and log
+    #[arg(long)]
+    pub out: Option<String>,
+    /// run without window and renderer, as fast as the CPU allows
+    #[arg(long)]
+    pub headless: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct LineageArgs {
+    /// lineage file written by training
+    pub input: String,
+    /// output DOT file, next to the input if not set
+    #[arg(long)]
+    pub out: Option<String>,
+}
+
+impl Cli {
+    /// build the config of this run.
+    ///
+    /// Errors if the config file or the file to load can't be read,
+    /// or the arguments are not valid.
+    pub fn to_config(&self) -> Result<SimConfig, Box<dyn Error>> {
+        match &self.command {
+            None => SimConfig::load_or_default(DEFAULT_CONFIG_PATH),
+            Some(Command::Demo(common)) => {
+                let mut config = common.load()?;
+                config.profile = Profile::Demo;
+                Ok(config)
+            }
+            Some(Command::Train(args)) => args.to_config(),
+            Some(Command::Lineage(_)) => Err("lineage does not run the simulation".into()),
+        }
+    }
+}
+
+impl LineageArgs {
+    pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        let records =
+            read_records(&self.input).map_err(|e| format!("failed to read {}: {}", self.input, e))?;
+        let out = match &self.out {
+            Some(out) => out.clone(),
+            None => Path::new(&self.input)
+                .with_extension("dot")
+                .to_string_lossy()
+                .into_owned(),
+        };
+        fs::write(&out, to_dot(&records))?;
+        println!("{} records exported to {}", records.len(), out);
+        Ok(())
+    }
+}
+
+impl CommonArgs {
+    fn load(&self) -> Result<SimConfig, Box<dyn Error>> {
+        match &self.config {
+            Some(path) => SimConfig::from_file(path)
+                .map_err(|e| format!("failed to load config {}: {}", path, e).into()),
+            None => SimConfig::load_or_default(DEFAULT_CONFIG_PATH),
+        }
+    }
+}
+
+impl TrainArgs {
+    fn to_config(&self) -> Result<SimConfig, Box<dyn Error>> {
+        let mut config = self.common.load()?;
+        config.profile = Profile::Move;
+        if self.headless {
+            config.headless = true;
+        }
+
+        let training = &mut config.training;
+        if let Some(mode) = self.mode {
+            training.mode = mode;
+        }
+        if let Some(population) = self.population {
+            training.population = population;
+        }
+        if self.generations.is_some() {
+            training.max_generations = self.generations;
+        }
+        if self.seed.is_some() {
+            training.seed = self.seed;
+        }
+        // fix the seed here, so the saved config can repeat the run
+        if training.seed.is_none() {
+            training.seed = Some(rand::random());
+        }
+
+        // population of 1 never trains
+        if training.population < 2 {
+            return Err("population must be at least 2".into());
+        }
+        if training.max_generations == Some(0) {
+            return Err("generations must be at least 1".into());
+        }
+
+        if let Some(load) = &self.load {
+            // fail early, before the window is created
+            let ef = read_export_file(load).map_err(|e| format!("failed to load {}: {}", load, e))?;
+            match self.population {
+                None => config.training.population = ef.len(),
+                Some(population) if population != ef.len() => {
+                    return Err(format!(
+                        "{} has {} blobs, but population is {}",
+                        load,
+                        ef.len(),
+                        population
+                    )
+                    .into());
+                }
+                _ => {}
+            }
+            config.io.load_fname = load.clone();
+            config.io.load_newest_file = false;
+            config.io.load_on_start = true;
+        }
+
+        if let Some(out) = &self.out {
+            fs::create_dir_all(out)?;
+            config.io.export_path = out.clone();
+            config.io.log_path = Path::new(out).join("run.log").to_string_lossy().into_owned();
+            // keep the config next to the outputs so the run can be repeated
+            fs::write(Path::new(out).join("config.toml"), toml::to_string(&config)?)?;
+        }
+
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod cli_test {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// file in the temp folder, unique to this test process
+    fn temp_file(name: &str, content: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("evosim_{}_{}", std::process::id(), name));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    /// `evosim train --config <config> args...`, so that `./evosim.toml` is not read
+    fn train(config: &Path, args: &[&str]) -> Result<SimConfig, Box<dyn Error>> {
+        let config = config.to_string_lossy();
+        let base = ["evosim", "train", "--config", &config];
+        Cli::parse_from(base.iter().chain(args.iter())).to_config()
+    }
+
+    #[test]
+    fn test_train_args_override_config() {
+        let path = temp_file(
+            "override.toml",
+            "[training]\nmode = \"swim\"\npopulation = 10\nhall_of_fame_size = 3\n",
+        );
+        let args = [
+            "--mode",
+            "walk",
+            "--population",
+            "60",
+            "--generations",
+            "500",
+            "--seed",
+            "42",
+        ];
+        let config = train(&path, &args);
+        fs::remove_file(path).unwrap();
+
+        let config = config.unwrap();
+        assert_eq!(config.profile, Profile::Move);
+        assert_eq!(config.training.mode, TrainingMode::Walk);
+        assert_eq!(config.training.population, 60);
+        assert_eq!(config.training.max_generations, Some(500));
+        assert_eq!(config.training.seed, Some(42));
+        // not given in arguments, kept from the file
+        assert_eq!(config.training.hall_of_fame_size, 3);
+    }
+
+    #[test]
+    fn test_invalid_population() {
+        let path = temp_file("invalid_population.toml", "");
+        let config = train(&path, &["--population", "1"]);
+        fs::remove_file(path).unwrap();
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn test_malformed_load_file() {
+        let path = temp_file("malformed.toml", "");
+        // one position but no blob
+        let load = temp_file(
+            "malformed_export.json",
+            r#"{"genovec":[],"nnvec":[],"posvec":[[0.0,0.0]]}"#,
+        );
+        let config = train(&path, &["--load", &load.to_string_lossy()]);
+        fs::remove_file(path).unwrap();
+        fs::remove_file(load).unwrap();
+        assert!(config.unwrap_err().to_string().contains("do not match"));
+    }
+}
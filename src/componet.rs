@@ -2,15 +2,23 @@
 
 use bevy::prelude::*;
 
+use crate::physics::{food::Food, world::Wall};
+
 /// Every collider should have a type flag.
 /// Then the sensor can know the collision type.
 #[derive(Debug, Component, Clone)]
 pub enum ColliderFlag {
     WALL,
-    BLOCK(BlobEntityIndex)
+    BLOCK(BlobEntityIndex),
+    FOOD,
 }
 
 /// denote which blob it belongs to.
 /// The u32 value is the idx value inside `Entity` class
 #[derive(Component, Clone, Debug)]
-pub struct BlobEntityIndex(pub Option<u32>);
\ No newline at end of file
+pub struct BlobEntityIndex(pub Option<u32>);
+
+/// colliders of blobs, despawned when blobs are refreshed.
+///
+/// walls and food stay in the world
+pub type BlobColliderFilter = (With<ColliderFlag>, Without<Wall>, Without<Food>);
\ No newline at end of file
@@ -0,0 +1,871 @@
+//! runtime configuration of the simulation
+//!
+//! every training knob lives in `SimConfig`, which is loaded from a TOML or JSON file
+//! at startup so that experiments don't need a recompile.
+//! Values missing in the file fall back to the defaults below.
+
+use std::{error::Error, f32::consts::PI, fs, path::Path, sync::OnceLock, time::Duration};
+
+use bevy::prelude::*;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use crate::{
+    brain::nn::Activation,
+    contorl::selection::{Objective, Selection},
+    consts::{
+        BRAIN_NN_INPUT_LEN, BRAIN_NN_OUTPUT_LEN, GENO_MAX_DEPTH, INWARD_NN_INPUT_LEN,
+        INWARD_NN_OUTPUT_LEN, OUTWARD_NN_INPUT_LEN, OUTWARD_NN_OUTPUT_LEN, SMELL_INPUT_LEN,
+        VISION_RAY_LEN,
+    },
+};
+
+/// process-wide copy of the config, set once in `main`
+static CONFIG: OnceLock<SimConfig> = OnceLock::new();
+
+/// install the config for the whole process.
+///
+/// Must be called before the app is built,
+/// calling it twice will panic.
+pub fn init_config(config: SimConfig) -> &'static SimConfig {
+    if CONFIG.set(config).is_err() {
+        panic!("SimConfig has already been initialized");
+    }
+    CONFIG.get().unwrap()
+}
+
+/// get the config of current process.
+///
+/// Bevy systems should use `Res<SimConfig>` instead,
+/// this is for helpers that can't reach the world (`Default` impls, logger, etc.)
+///
+/// fall back to `SimConfig::default()` if `init_config` was never called (tests)
+pub fn config() -> &'static SimConfig {
+    CONFIG.get_or_init(SimConfig::default)
+}
+
+/// Bevy resource holding all the runtime configuration
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimConfig {
+    /// choose which mutation profile in `profiles` to use,
+    /// and whether to run the demo setup or movement training
+    pub profile: Profile,
+    /// thread count, `None` means automatic
+    pub thread_count: Option<usize>,
+    /// run without window and renderer, for machines without GPU
+    pub headless: bool,
+    pub physics: PhysicsConfig,
+    pub world: WorldConfig,
+    pub training: TrainingConfig,
+    pub speciation: SpeciationConfig,
+    pub novelty: NoveltyConfig,
+    pub map_elites: MapElitesConfig,
+    pub islands: IslandConfig,
+    pub nn: NNConfig,
+    pub vision: VisionConfig,
+    pub energy: EnergyConfig,
+    pub food: FoodConfig,
+    pub profiles: Profiles,
+    pub io: IOConfig,
+    pub keys: KeyConfig,
+    pub debug: DebugConfig,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            profile: Profile::Move,
+            thread_count: None,
+            headless: false,
+            physics: PhysicsConfig::default(),
+            world: WorldConfig::default(),
+            training: TrainingConfig::default(),
+            speciation: SpeciationConfig::default(),
+            novelty: NoveltyConfig::default(),
+            map_elites: MapElitesConfig::default(),
+            islands: IslandConfig::default(),
+            nn: NNConfig::default(),
+            vision: VisionConfig::default(),
+            energy: EnergyConfig::default(),
+            food: FoodConfig::default(),
+            profiles: Profiles::default(),
+            io: IOConfig::default(),
+            keys: KeyConfig::default(),
+            debug: DebugConfig::default(),
+        }
+    }
+}
+
+impl SimConfig {
+    /// load config from a `.toml` or `.json` file.
+    ///
+    /// The format is decided by the file extension, anything else is parsed as TOML.
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        let file_str = fs::read_to_string(path)?;
+        let config = match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str::<SimConfig>(&file_str)?,
+            _ => toml::from_str::<SimConfig>(&file_str)?,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// reject values that break the simulation, instead of failing mid-run
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        // energy input is a ratio of it, also rejects NaN
+        let initial = self.energy.initial;
+        if !(initial > 0.0 && initial.is_finite()) {
+            return Err(format!("energy.initial must be positive, got {}", initial).into());
+        }
+        Ok(())
+    }
+
+    /// load config from file if it exists, otherwise use the default values
+    pub fn load_or_default(path: &str) -> Result<Self, Box<dyn Error>> {
+        if Path::new(path).exists() {
+            Self::from_file(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    /// mutation parameters of the selected profile
+    pub fn mutate(&self) -> &MutateConfig {
+        match self.profile {
+            Profile::Demo => &self.profiles.demo,
+            Profile::Move => &self.profiles.train_move,
+        }
+    }
+
+    /// world size `[width, height]` of current training mode
+    pub fn world_size(&self) -> [f32; 2] {
+        match self.training.mode {
+            TrainingMode::Swim | TrainingMode::Forage => {
+                [self.world.swim_width, self.world.swim_height]
+            }
+            TrainingMode::Walk => [self.world.walk_width, self.world.walk_height],
+        }
+    }
+
+    /// y of the ground in walk mode, `None` when swimming
+    pub fn ground_y(&self) -> Option<f32> {
+        match self.training.mode {
+            TrainingMode::Swim | TrainingMode::Forage => None,
+            TrainingMode::Walk => Some(-self.world.walk_height / 2.0),
+        }
+    }
+
+    /// shape of inward NNs, inputs of enabled sensors included
+    pub fn inward_shape(&self) -> Vec<usize> {
+        let input = INWARD_NN_INPUT_LEN + self.nn.oscillator as usize + self.vision.input_len();
+        full_shape(input, &self.nn.inward_hidden, INWARD_NN_OUTPUT_LEN)
+    }
+
+    pub fn outward_shape(&self) -> Vec<usize> {
+        let input = OUTWARD_NN_INPUT_LEN + self.nn.oscillator as usize;
+        full_shape(input, &self.nn.outward_hidden, OUTWARD_NN_OUTPUT_LEN)
+    }
+
+    pub fn brain_shape(&self) -> Vec<usize> {
+        let smell = match self.training.mode {
+            TrainingMode::Forage => SMELL_INPUT_LEN,
+            _ => 0,
+        };
+        let input =
+            BRAIN_NN_INPUT_LEN + smell + self.energy.input_len() + self.vision.input_len();
+        full_shape(input, &self.nn.brain_hidden, BRAIN_NN_OUTPUT_LEN)
+    }
+}
+
+/// profiles replace the old `demo` and `move` cargo features
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    /// simple rand demo
+    Demo,
+    /// training to learn to move
+    Move,
+}
+
+/// choose between swim, walk and forage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TrainingMode {
+    Swim,
+    Walk,
+    /// swim and eat food, see `FoodConfig`
+    Forage,
+}
+
+/// timestep and joint motor config
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PhysicsConfig {
+    pub rapier_dt: f32,
+    pub rapier_substeps: usize,
+    pub motor_stiffness: f32,
+    pub motor_damping: f32,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        Self {
+            rapier_dt: 1.0 / 60.0,
+            rapier_substeps: 1,
+            motor_stiffness: 10.0,
+            motor_damping: 0.0,
+        }
+    }
+}
+
+/// world size for each training mode
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorldConfig {
+    pub walk_width: f32,
+    pub walk_height: f32,
+    pub swim_width: f32,
+    pub swim_height: f32,
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        Self {
+            walk_width: 100000.0,
+            walk_height: 2000.0,
+            swim_width: 10000.0,
+            swim_height: 10000.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrainingConfig {
+    pub mode: TrainingMode,
+    /// population for each training iteration
+    pub population: usize,
+    /// how long a signle iteration, counted in frame
+    pub iteration_length: usize,
+    /// save a checkpoint every `checkpoints_length` iterations
+    pub checkpoints_length: usize,
+    /// survival rate in `train_move.rs`
+    pub survival_rate: f32,
+    /// how survivers are selected
+    pub selection: Selection,
+    /// tournament selection hybrid, for `Selection::Hybrid`
+    pub hybrid_rate: f32,
+    /// objectives for `Selection::Nsga2`
+    pub objectives: Vec<Objective>,
+    /// copy the best `elite_count` blobs (of each island) into next generation without mutation
+    pub elite_count: usize,
+    /// how many best blobs ever seen are kept in the hall of fame, 0 to disable
+    pub hall_of_fame_size: usize,
+    /// chance that a reproduced blob is the crossover of two survivers
+    /// instead of a clone of one, off by default
+    pub crossover_rate: f32,
+    /// chance that a block shared by both crossover parents mixes their NN weights
+    pub nn_crossover_prob: f32,
+    /// limit for population generation area
+    ///
+    /// 100*100 world size with 0.5 ratio result in 50*50 generation area
+    pub scatter_ratio_x: f32,
+    pub scatter_ratio_y: f32,
+    /// min distance between two spawn point
+    pub spawn_point_radius: f32,
+    /// exit the app after this many generations, `None` means run forever
+    pub max_generations: Option<usize>,
+    /// seed of the run, random if `None`
+    pub seed: Option<u64>,
+}
+
+impl Default for TrainingConfig {
+    fn default() -> Self {
+        Self {
+            mode: TrainingMode::Swim,
+            population: 30,
+            iteration_length: 1000,
+            checkpoints_length: 100,
+            survival_rate: 0.5,
+            selection: Selection::Hybrid,
+            hybrid_rate: 0.3,
+            objectives: vec![Objective::Fitness, Objective::Crowding],
+            elite_count: 0,
+            hall_of_fame_size: 10,
+            crossover_rate: 0.0,
+            nn_crossover_prob: 0.5,
+            scatter_ratio_x: 0.8,
+            scatter_ratio_y: 0.8,
+            spawn_point_radius: 750.0,
+            max_generations: None,
+            seed: None,
+        }
+    }
+}
+
+/// speciation and fitness sharing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpeciationConfig {
+    pub enabled: bool,
+    /// the threshold adjusts each generation to hit this species count
+    pub target_species: usize,
+    /// initial compatibility threshold, blobs closer than it are in the same species
+    pub threshold: f32,
+    pub threshold_step: f32,
+    pub min_threshold: f32,
+    /// weight of the tree edit distance in blob distance
+    pub ted_weight: f32,
+    /// weight of the mean NN weight distance in blob distance
+    pub nn_weight: f32,
+}
+
+impl Default for SpeciationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            target_species: 5,
+            threshold: 3.0,
+            threshold_step: 0.3,
+            min_threshold: 0.3,
+            ted_weight: 1.0,
+            nn_weight: 1.0,
+        }
+    }
+}
+
+/// novelty search, reward blobs that behave differently from the population and archive
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NoveltyConfig {
+    pub enabled: bool,
+    /// blend of novelty and fitness, `1.0` is novelty alone, `0.0` is fitness alone
+    pub weight: f32,
+    /// novelty is the mean distance to the k nearest neighbours
+    pub k: usize,
+    /// how many of the most novel blobs are added to the archive each generation
+    pub archive_add: usize,
+    /// the oldest descriptors are dropped when the archive is full
+    pub archive_size: usize,
+    /// points sampled from the `mass_center` trajectory
+    pub trajectory_samples: usize,
+    /// scale of the joint angle statistics, so that they are comparable to positions
+    pub joint_weight: f32,
+}
+
+impl Default for NoveltyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            weight: 1.0,
+            k: 15,
+            archive_add: 2,
+            archive_size: 500,
+            trajectory_samples: 8,
+            joint_weight: 100.0,
+        }
+    }
+}
+
+/// grid of the MAP-Elites archive, for `Selection::MapElites`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MapElitesConfig {
+    pub block_count: GridAxis,
+    /// depth of the deepest block in geno tree
+    pub depth: GridAxis,
+    /// ratio of limbs with a left-right mirror, in `[0, 1]`
+    pub symmetry: GridAxis,
+    /// total area of all blocks
+    pub area: GridAxis,
+}
+
+impl Default for MapElitesConfig {
+    fn default() -> Self {
+        Self {
+            block_count: GridAxis::new(1.0, 17.0, 8),
+            depth: GridAxis::new(0.0, GENO_MAX_DEPTH as f32, GENO_MAX_DEPTH as usize),
+            symmetry: GridAxis::new(0.0, 1.0, 5),
+            area: GridAxis::new(0.0, 200000.0, 10),
+        }
+    }
+}
+
+/// a feature axis of the grid, `[min, max)` split into `bins` equal bins
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GridAxis {
+    pub min: f32,
+    pub max: f32,
+    pub bins: usize,
+}
+
+impl GridAxis {
+    pub fn new(min: f32, max: f32, bins: usize) -> Self {
+        Self { min, max, bins }
+    }
+}
+
+/// island model, the population is split into islands that select and reproduce separately.
+///
+/// islands are vertical stripes of the spawn area
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IslandConfig {
+    /// number of islands, `1` is a single population
+    pub count: usize,
+    /// generations between migrations, `0` means never migrate
+    pub migration_interval: usize,
+    /// ratio of survivers of each island that migrate to the next one
+    pub migration_rate: f32,
+}
+
+impl Default for IslandConfig {
+    fn default() -> Self {
+        Self {
+            count: 1,
+            migration_interval: 10,
+            migration_rate: 0.1,
+        }
+    }
+}
+
+/// hidden layers and activation of the networks.
+///
+/// input and output length are decided by the signals, so they stay in `consts.rs`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NNConfig {
+    pub inward_hidden: Vec<usize>,
+    pub outward_hidden: Vec<usize>,
+    pub brain_hidden: Vec<usize>,
+    /// activation of hidden layers, ReLU will make all output positive
+    pub activation: Activation,
+    /// activation of output layers, `Tanh` lets blocks command negative motor targets
+    pub output_activation: Activation,
+    /// hidden layers feed their last output back (Elman network),
+    /// so blobs can keep rhythms without sensory feedback
+    pub recurrent: bool,
+    /// each block has a sine oscillator as an extra input of its NNs
+    pub oscillator: bool,
+    /// new networks evolve their topology (NEAT) and start without hidden nodes,
+    /// hidden sizes and `recurrent` are ignored for them
+    pub neat: bool,
+    /// stack weights of fixed-layer networks with the same shape
+    /// into one batched matmul per layer
+    pub batched: bool,
+}
+
+impl Default for NNConfig {
+    fn default() -> Self {
+        Self {
+            inward_hidden: vec![8],
+            outward_hidden: vec![8],
+            brain_hidden: vec![8],
+            activation: Activation::Sigmoid,
+            output_activation: Activation::Sigmoid,
+            recurrent: false,
+            oscillator: false,
+            neat: false,
+            batched: false,
+        }
+    }
+}
+
+/// raycast vision of blocks, see `contorl::vision`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VisionConfig {
+    /// each block and brain casts rays and feeds what they hit to its NN
+    pub enabled: bool,
+    /// rays of each block, spread evenly over `fov`
+    pub rays: usize,
+    /// field of view in radians, centred on the free face of the block
+    pub fov: f32,
+    /// max ray length, anything further is not seen
+    pub range: f32,
+}
+
+impl Default for VisionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            rays: 3,
+            fov: PI / 2.0,
+            range: 500.0,
+        }
+    }
+}
+
+impl VisionConfig {
+    /// extra NN inputs of vision, `0` if disabled
+    pub fn input_len(&self) -> usize {
+        if self.enabled {
+            self.rays * VISION_RAY_LEN
+        } else {
+            0
+        }
+    }
+}
+
+/// energy budget of blobs, see `update_energy`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnergyConfig {
+    /// blobs spend energy on motors and mass, and their motors stop when it runs out.
+    ///
+    /// The energy left is an extra input of the brain NN
+    pub enabled: bool,
+    /// energy of a newly spawned blob
+    pub initial: f32,
+    /// energy per unit of motor work
+    pub motor_cost: f32,
+    /// energy per unit of block mass each second
+    pub mass_cost: f32,
+    /// divide fitness by the energy spent, `score / (1 + energy_used)`,
+    /// negative scores are multiplied instead
+    pub efficiency_fitness: bool,
+}
+
+impl Default for EnergyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            initial: 100.0,
+            motor_cost: 0.01,
+            mass_cost: 0.0001,
+            efficiency_fitness: false,
+        }
+    }
+}
+
+impl EnergyConfig {
+    /// extra brain NN input, `0` if disabled
+    pub fn input_len(&self) -> usize {
+        self.enabled as usize
+    }
+}
+
+/// food of `TrainingMode::Forage`, see `physics::food`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FoodConfig {
+    /// food count per 1000 * 1000 area, kept by growing food back
+    pub density: f32,
+    /// chance per second for a food to grow back while below `density`
+    pub respawn_rate: f32,
+    /// food grows around this many fixed centers, `0` spreads it evenly
+    pub patches: usize,
+    /// std of the distance between a food and its patch center
+    pub patch_radius: f32,
+    pub radius: f32,
+    /// energy gained by eating a food, see `EnergyConfig`
+    pub energy: f32,
+    /// smell of a food fades like a gaussian with this std
+    pub smell_range: f32,
+}
+
+impl Default for FoodConfig {
+    fn default() -> Self {
+        Self {
+            density: 2.0,
+            respawn_rate: 2.0,
+            patches: 4,
+            patch_radius: 800.0,
+            radius: 30.0,
+            energy: 20.0,
+            smell_range: 1500.0,
+        }
+    }
+}
+
+/// `[input, hidden..., output]`
+fn full_shape(input: usize, hidden: &Vec<usize>, output: usize) -> Vec<usize> {
+    std::iter::once(input)
+        .chain(hidden.iter().cloned())
+        .chain(std::iter::once(output))
+        .collect()
+}
+
+/// mutation parameters for each profile
+#[derive(Debug, Clone, Serialize)]
+pub struct Profiles {
+    pub demo: MutateConfig,
+    #[serde(rename = "move")]
+    pub train_move: MutateConfig,
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        Self {
+            demo: MutateConfig::demo(),
+            train_move: MutateConfig::train_move(),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Profiles {
+    /// each profile falls back to its own defaults,
+    /// so a file only needs to list the fields it changes
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize, Default)]
+        #[serde(default)]
+        struct RawProfiles {
+            demo: Option<Value>,
+            #[serde(rename = "move")]
+            train_move: Option<Value>,
+        }
+
+        let raw = RawProfiles::deserialize(deserializer)?;
+        Ok(Self {
+            demo: overlay(MutateConfig::demo(), raw.demo).map_err(de::Error::custom)?,
+            train_move: overlay(MutateConfig::train_move(), raw.train_move)
+                .map_err(de::Error::custom)?,
+        })
+    }
+}
+
+/// overwrite fields of `base` with the ones that exist in `patch`
+fn overlay(base: MutateConfig, patch: Option<Value>) -> Result<MutateConfig, serde_json::Error> {
+    let mut value = serde_json::to_value(base)?;
+    if let (Value::Object(base), Some(Value::Object(patch))) = (&mut value, patch) {
+        base.extend(patch);
+    }
+    serde_json::from_value(value)
+}
+
+/// parameters used in `mutate` module
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MutateConfig {
+    /// probablity of having tree structure mutate
+    ///
+    /// if the tree structure is going to mutate, maximumly 1 node will mutate
+    /// since single node blob can't lose a node anymore
+    pub tree_structure_prob: f32,
+    /// probablity for the choosen node to gain a child node,
+    /// otherwise the blob gonna lose a limb
+    pub gain_limb_prob: f32,
+    /// max times to retry to add a new limb if last one cause self-conflict
+    ///
+    /// condition of impossible new limb exist (the parent indicator was dropped)
+    pub gain_limb_max_try: u32,
+    /// probablity of having limb size mutate
+    pub block_size_prob: f32,
+    /// probablity for each signle block to mutate
+    ///
+    /// mutation is not garenteed since it might cause self-confliction
+    pub single_block_size_prob: f32,
+    /// scaler for block mutation
+    pub single_block_size_scaler: [f32; 2],
+    /// clamp between this scaler for `DEFAULT_BLOCK_SIZE`
+    pub single_block_size_clamp_scaler: [f32; 2],
+    /// porbablity of a signle joint limit to mutate
+    pub joint_limit_prob: f32,
+    pub joint_limit_min: f32,
+    pub joint_limit_max: f32,
+    /// porbablity of a single nn to mutate
+    pub nn_prob: f32,
+    /// standard deviation for normal distribution mutation
+    pub nn_std: f32,
+    /// probablity of a single weight to mutate after the `BaseNN` is chosen to be mutate.
+    pub nn_weight_prob: f32,
+    /// probablity of a single bias to mutate after the `BaseNN` is chosen to be mutate.
+    pub nn_bias_prob: f32,
+    /// probablity of a single layer to switch to a random activation
+    /// after the `BaseNN` is chosen to be mutate, `0.0` keeps activations fixed
+    pub nn_activation_prob: f32,
+    /// probablity of a NEAT network to split a connection with a new node
+    pub neat_add_node_prob: f32,
+    /// probablity of a NEAT network to connect two nodes
+    pub neat_add_connection_prob: f32,
+}
+
+impl MutateConfig {
+    /// mutate for demo
+    pub fn demo() -> Self {
+        Self {
+            tree_structure_prob: 0.9,
+            gain_limb_prob: 0.5,
+            gain_limb_max_try: 10,
+            block_size_prob: 1.0,
+            single_block_size_prob: 0.5,
+            single_block_size_scaler: [0.9, 1.1],
+            single_block_size_clamp_scaler: [0.5, 2.0],
+            joint_limit_prob: 0.5,
+            joint_limit_min: -std::f32::consts::PI * 0.9,
+            joint_limit_max: std::f32::consts::PI * 0.9,
+            nn_prob: 0.5,
+            nn_std: 0.1,
+            nn_weight_prob: 0.8,
+            nn_bias_prob: 0.8,
+            nn_activation_prob: 0.0,
+            neat_add_node_prob: 0.3,
+            neat_add_connection_prob: 0.5,
+        }
+    }
+
+    /// mutate for move training
+    pub fn train_move() -> Self {
+        Self {
+            tree_structure_prob: 0.05,
+            gain_limb_prob: 0.5,
+            gain_limb_max_try: 10,
+            block_size_prob: 0.25,
+            single_block_size_prob: 0.5,
+            single_block_size_scaler: [0.7, 1.3],
+            single_block_size_clamp_scaler: [0.3, 2.0],
+            joint_limit_prob: 0.1,
+            joint_limit_min: -std::f32::consts::PI * 0.9,
+            joint_limit_max: std::f32::consts::PI * 0.9,
+            nn_prob: 0.25,
+            nn_std: 0.15,
+            nn_weight_prob: 0.8,
+            nn_bias_prob: 0.8,
+            nn_activation_prob: 0.0,
+            neat_add_node_prob: 0.03,
+            neat_add_connection_prob: 0.05,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IOConfig {
+    pub export_path: String,
+    pub load_folder: String,
+    pub load_fname: String,
+    pub load_newest_file: bool,
+    /// build the population from `load_fname` at startup instead of random blobs,
+    /// the run fails if the file can't be loaded
+    pub load_on_start: bool,
+    pub log_path: String,
+}
+
+impl IOConfig {
+    /// folder of `load_fname`, the files a run saved beside its export are loaded from here
+    pub fn load_fname_folder(&self) -> &Path {
+        Path::new(&self.load_fname)
+            .parent()
+            .unwrap_or_else(|| Path::new(&self.load_folder))
+    }
+}
+
+impl Default for IOConfig {
+    fn default() -> Self {
+        Self {
+            export_path: "./export/".to_string(),
+            load_folder: "./export/".to_string(),
+            load_fname: "./export/2023-07-25T15-28-56.json".to_string(),
+            load_newest_file: true,
+            load_on_start: false,
+            log_path: "./run.log".to_string(),
+        }
+    }
+}
+
+/// user contorl
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyConfig {
+    pub mutate_and_refresh: KeyCode,
+    pub new_iteration: KeyCode,
+    pub auto_no_vsync: KeyCode,
+    pub save_all_blobs_to_json: KeyCode,
+    pub load_all_blobs_from_json: KeyCode,
+    pub clean_all_blobs: KeyCode,
+    /// dump recorded NN activity to csv, see `DebugConfig::record_activity`
+    pub dump_activity: KeyCode,
+}
+
+impl Default for KeyConfig {
+    fn default() -> Self {
+        Self {
+            mutate_and_refresh: KeyCode::M,
+            new_iteration: KeyCode::R,
+            auto_no_vsync: KeyCode::V,
+            save_all_blobs_to_json: KeyCode::S,
+            load_all_blobs_from_json: KeyCode::L,
+            clean_all_blobs: KeyCode::X,
+            dump_activity: KeyCode::A,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DebugConfig {
+    pub print_function_time: bool,
+    /// min time cost (in microseconds) each frame to be print
+    pub min_print_duration_us: u64,
+    /// record inputs and outputs of block NNs, see `ActivityRecorder`
+    pub record_activity: bool,
+    /// frames of activity to keep
+    pub activity_window: u32,
+}
+
+impl Default for DebugConfig {
+    fn default() -> Self {
+        Self {
+            print_function_time: false,
+            min_print_duration_us: 500,
+            record_activity: false,
+            activity_window: 600,
+        }
+    }
+}
+
+impl DebugConfig {
+    /// whether a function taking `duration` should be printed
+    pub fn should_print(&self, duration: Duration) -> bool {
+        self.print_function_time && duration >= Duration::from_micros(self.min_print_duration_us)
+    }
+}
+
+#[cfg(test)]
+mod config_test {
+    use super::*;
+
+    #[test]
+    fn test_partial_toml_uses_defaults() {
+        let config: SimConfig = toml::from_str(
+            r#"
+            profile = "demo"
+
+            [training]
+            mode = "walk"
+            population = 60
+
+            [profiles.demo]
+            nn_std = 0.2
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.profile, Profile::Demo);
+        assert_eq!(config.training.mode, TrainingMode::Walk);
+        assert_eq!(config.training.population, 60);
+        assert_eq!(config.training.iteration_length, 1000);
+        assert_eq!(config.mutate().nn_std, 0.2);
+        assert_eq!(config.mutate().tree_structure_prob, 0.9);
+        assert_eq!(config.world_size(), [100000.0, 2000.0]);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let config = SimConfig::default();
+        let file_str = serde_json::to_string(&config).unwrap();
+        let loaded: SimConfig = serde_json::from_str(&file_str).unwrap();
+        assert_eq!(loaded.inward_shape(), config.inward_shape());
+        assert_eq!(loaded.keys.new_iteration, KeyCode::R);
+    }
+
+    #[test]
+    fn test_validate_energy() {
+        assert!(SimConfig::default().validate().is_ok());
+        for initial in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            let mut config = SimConfig::default();
+            config.energy.initial = initial;
+            assert!(config.validate().is_err());
+        }
+    }
+}
@@ -2,26 +2,37 @@
 //! 
 //! Implementation of `BlobContorlPlugin`
 
-use bevy::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use bevy::{app::AppExit, prelude::*};
 use rand::prelude::*;
 
 use crate::{
     blob::geno_blob_builder::{BlobGeno, GenoBlobBuilder},
-    brain::resource::BevyBlockNeurons,
+    brain::{neat::Innovations, resource::BevyBlockNeurons},
+    config::{config, Profile, SimConfig},
     consts::*,
+    io::import::{overwrite, read_population_file},
+    rng::SimRng,
     contorl::{
         resource::{Frames, TED},
-        train_move::{log_train_move_swim, train_move_swim},
+        selection::Selection,
+        train_move::{log_train_move, train_move},
         update::{update_crowding_distance, update_iteration_frames},
     },
-    logger_info,
+    logger_error, logger_info, logger_warn,
     mutate::mutate::mutate_and_refresh_after_train,
 };
 
 use super::{
+    fitness::TrainFitness,
     resource::TrainMutPipe,
-    train_move::{log_train_move_walk, train_move_walk},
-    update::{block_action, update_blob_info, update_joint_info},
+    hall_of_fame::HallOfFame,
+    lineage::LineageTracker,
+    map_elites::MapElites,
+    novelty::NoveltyArchive,
+    species::Species,
+    update::{block_action, update_blob_info, update_energy, update_joint_info},
 };
 
 /// Main entrance of the whole EvoSim system
@@ -46,43 +57,45 @@ use super::{
 /// - `TrainMutPipe`
 /// - `Frames`
 /// - `TED`
+/// - `Species`
+/// - `HallOfFame`
+/// - `LineageTracker`
+/// - `NoveltyArchive`
+/// - `MapElites`
+/// - `Innovations`
 ///
 ///
 /// implement all training style.
-/// choose between different training mode in const
+/// choose between different training mode in `SimConfig`,
+/// which decides the `Fitness` used by selection, logging and export
 pub struct BlobContorlPlugin;
 
 impl Plugin for BlobContorlPlugin {
-    #[cfg(feature = "demo")]
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, demo_setup)
-            .add_systems(Update, (block_action, update_blob_info, update_joint_info));
-    }
+        let config = config();
+        // training mode decides the fitness function
+        let mut fitness = TrainFitness::from_mode(config.training.mode);
+        if config.energy.enabled && config.energy.efficiency_fitness {
+            fitness = fitness.per_energy();
+        }
+        app.insert_resource(fitness);
 
-    #[cfg(feature = "move")]
-    fn build(&self, app: &mut App) {
-        if TRAINING_MODE == "swim" {
-            // train swim
-            app.add_systems(Startup, move_setup)
-                .add_systems(
-                    Update,
-                    (
-                        update_iteration_frames.before(update_blob_info),
-                        block_action,
-                        update_blob_info,
-                        update_joint_info,
-                        update_crowding_distance,
-                        log_train_move_swim.after(block_action),
-                        train_move_swim.after(log_train_move_swim),
-                        mutate_and_refresh_after_train.after(train_move_swim),
-                    ),
-                )
-                .init_resource::<TrainMutPipe>()
-                .init_resource::<Frames>()
-                .init_resource::<TED>();
-        } else if TRAINING_MODE == "walk" {
-            // train walk
-            app.add_systems(Startup, move_setup)
+        // parents come from the archive, so the survivers are not used
+        if config.profile == Profile::Move && config.training.selection == Selection::MapElites {
+            let training = &config.training;
+            for (ignored, name) in [
+                (training.elite_count > 0, "elite_count"),
+                (config.novelty.enabled, "novelty"),
+                (config.speciation.enabled, "speciation"),
+            ] {
+                if ignored {
+                    logger_warn!("{} is ignored by map_elites selection", name);
+                }
+            }
+        }
+
+        if config.profile == Profile::Demo {
+            app.add_systems(Startup, demo_setup)
                 .add_systems(
                     Update,
                     (
@@ -90,18 +103,43 @@ impl Plugin for BlobContorlPlugin {
                         block_action,
                         update_blob_info,
                         update_joint_info,
-                        update_crowding_distance,
-                        log_train_move_walk.after(block_action),
-                        train_move_walk.after(log_train_move_walk),
-                        mutate_and_refresh_after_train.after(train_move_walk),
+                        update_energy.after(block_action),
                     ),
                 )
-                .init_resource::<TrainMutPipe>()
-                .init_resource::<Frames>()
-                .init_resource::<TED>();
-        } else {
-            panic!()
+                .init_resource::<Frames>();
+            return;
         }
+
+        // archives loaded from files register their NEAT genes
+        let mut innovations = Innovations::default();
+        app.add_systems(Startup, move_setup)
+            .add_systems(
+                Update,
+                (
+                    update_iteration_frames.before(update_blob_info),
+                    block_action,
+                    update_blob_info,
+                    update_joint_info,
+                    update_energy.after(block_action),
+                    update_crowding_distance,
+                    log_train_move.after(block_action),
+                    train_move.after(log_train_move),
+                    mutate_and_refresh_after_train.after(train_move),
+                    exit_after_generations.after(mutate_and_refresh_after_train),
+                ),
+            )
+            .init_resource::<TrainMutPipe>()
+            .init_resource::<Frames>()
+            .init_resource::<TED>()
+            .insert_resource(HallOfFame::from_config(&config.io, &mut innovations))
+            .insert_resource(LineageTracker::new(
+                &config.io.export_path,
+                config.io.load_on_start,
+            ))
+            .init_resource::<NoveltyArchive>()
+            .insert_resource(MapElites::from_config(&config.io, &mut innovations))
+            .insert_resource(Species::new(&config.speciation))
+            .insert_resource(innovations);
     }
 
     fn finish(&self, _app: &mut App) {
@@ -110,8 +148,15 @@ impl Plugin for BlobContorlPlugin {
 }
 
 /// inital setup for demo (mainly for mutation demo)
-pub fn demo_setup(commands: Commands, mut bbns: ResMut<BevyBlockNeurons>) {
-    let mut builder = GenoBlobBuilder::from_commands(commands, &mut bbns.nnvec);
+pub fn demo_setup(
+    commands: Commands,
+    mut bbns: ResMut<BevyBlockNeurons>,
+    mut innovations: ResMut<Innovations>,
+    mut rng: ResMut<SimRng>,
+) {
+    let rng = &mut *rng;
+    let mut builder =
+        GenoBlobBuilder::from_commands(commands, &mut bbns.nnvec, &mut innovations, &mut rng.nn);
     // let mut geno = BlobGeno::new_rand();
     // builder.build(&mut geno, [-500.0, 0.0]);
     // println!("{:#?}",geno);
@@ -119,49 +164,126 @@ pub fn demo_setup(commands: Commands, mut bbns: ResMut<BevyBlockNeurons>) {
     for i in -2..2 {
         for j in -2..2 {
             builder.build(
-                &mut BlobGeno::new_rand(),
+                &mut BlobGeno::new_rand(&mut rng.geno),
                 [1000.0 * i as f32, 1000.0 * j as f32],
             );
         }
     }
 }
 
+/// set when the run stops on an error, `main` then exits with a failure code
+pub static RUN_FAILED: AtomicBool = AtomicBool::new(false);
+
 /// inital setup for movement training
-pub fn move_setup(commands: Commands, mut bbns: ResMut<BevyBlockNeurons>) {
-    let mut builder = GenoBlobBuilder::from_commands(commands, &mut bbns.nnvec);
+///
+/// start from random blobs, or from `load_fname` if `load_on_start` is set.
+/// If the file can't be loaded the run stops and is marked as failed in `RUN_FAILED`
+pub fn move_setup(
+    commands: Commands,
+    mut bbns: ResMut<BevyBlockNeurons>,
+    config: Res<SimConfig>,
+    mut innovations: ResMut<Innovations>,
+    mut rng: ResMut<SimRng>,
+    mut exit: EventWriter<AppExit>,
+) {
+    logger_info!(
+        "training started, mode {:?}, population {}, seed {}",
+        config.training.mode,
+        config.training.population,
+        rng.seed()
+    );
+
+    if config.io.load_on_start {
+        let io = &config.io;
+        match read_population_file(&io.load_fname, config.training.population) {
+            Ok(ef) => {
+                logger_info!("population loaded from {}", io.load_fname);
+                overwrite(ef, commands, &mut bbns, &mut innovations, &mut rng.nn);
+                return;
+            }
+            Err(e) => {
+                logger_error!("failed to load {}: {}", io.load_fname, e);
+                RUN_FAILED.store(true, Ordering::Relaxed);
+                exit.send(AppExit);
+                return;
+            }
+        }
+    }
+
+    let rng = &mut *rng;
+    let mut builder =
+        GenoBlobBuilder::from_commands(commands, &mut bbns.nnvec, &mut innovations, &mut rng.nn);
 
-    let centers = get_center();
+    let centers = get_center(&config, &mut rng.spawn);
     for center in centers.iter() {
-        builder.build(&mut BlobGeno::new_rand(), [center.0, center.1]);
+        builder.build(&mut BlobGeno::new_rand(&mut rng.geno), [center.0, center.1]);
+    }
+}
+
+/// exit the app after `max_generations` generations are trained
+pub fn exit_after_generations(
+    frames: Res<Frames>,
+    config: Res<SimConfig>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if let Some(max_generations) = config.training.max_generations {
+        if frames.0 >= (max_generations * config.training.iteration_length) as u128 {
+            logger_info!("{} generations trained, exit", max_generations);
+            exit.send(AppExit);
+        }
     }
 }
 
 /// generate a random blob center pos base on target population
 ///
-/// centers generation is contorled by several consts.
+/// centers generation is contorled by `TrainingConfig`.
+/// Centers are ordered by island, see `get_island_center`
 ///
 /// function will panic if it is not very likely to
 /// fit all blobs into the given field
-pub fn get_center() -> Vec<(f32, f32)> {
-    let mut rng: ThreadRng = thread_rng();
-
-    let mut world_width = WORLD_WIDTH_SWIM as f32;
-    let mut world_height = WORLD_HEIGHT_SWIM as f32;
-    if TRAINING_MODE == "walk" {
-        world_width = WORLD_WIDTH_WALK as f32;
-        world_height = WORLD_HEIGHT_WALK as f32;
+pub fn get_center(config: &SimConfig, rng: &mut impl Rng) -> Vec<(f32, f32)> {
+    let mut points = Vec::<(f32, f32)>::new();
+    for island in 0..island_sizes(config).len() {
+        points.append(&mut get_island_center(config, island, rng));
     }
+    points
+}
+
+/// population of each island, the remainder goes to the first islands
+pub fn island_sizes(config: &SimConfig) -> Vec<usize> {
+    let population = config.training.population;
+    let count = config.islands.count.clamp(1, population.max(1));
+    (0..count)
+        .map(|island| population / count + usize::from(island < population % count))
+        .collect()
+}
+
+/// generate random blob center pos for an island,
+/// islands split the generation area into vertical stripes from left to right
+///
+/// function will panic if it is not very likely to
+/// fit all blobs into the given field
+pub fn get_island_center(
+    config: &SimConfig,
+    island: usize,
+    rng: &mut impl Rng,
+) -> Vec<(f32, f32)> {
+    let [world_width, world_height] = config.world_size();
+    let training = &config.training;
+    let sizes = island_sizes(config);
 
+    let width = world_width * training.scatter_ratio_x;
+    let stripe = width / sizes.len() as f32;
     let x_lim: (f32, f32) = (
-        -world_width * SCATTER_RATIO_X * 0.5,
-        world_width as f32 * SCATTER_RATIO_X * 0.5,
+        -width * 0.5 + stripe * island as f32,
+        -width * 0.5 + stripe * (island + 1) as f32,
     );
     let y_lim: (f32, f32) = (
-        -world_height as f32 * SCATTER_RATIO_Y * 0.5,
-        world_height as f32 * SCATTER_RATIO_Y * 0.5,
+        -world_height * training.scatter_ratio_y * 0.5,
+        world_height * training.scatter_ratio_y * 0.5,
     );
-    let number: usize = POPULATION;
-    let min_distance: f32 = BLOB_SPAWN_POINT_RADIUS;
+    let number: usize = sizes[island];
+    let min_distance: f32 = training.spawn_point_radius;
 
     let mut points: Vec<(f32, f32)> = Vec::new();
 
@@ -0,0 +1,143 @@
+//! fitness functions for training.
+//!
+//! Selection, logging and export only see the `Fitness` trait,
+//! a new task only needs a new implementation and a `TrainingMode` to choose it.
+
+use bevy::prelude::*;
+
+use crate::{
+    blob::{blob::BlobInfo, geno_blob_builder::BlobGeno},
+    config::TrainingMode,
+};
+
+/// score a blob at the end of an iteration, higher is better.
+///
+/// `BlobInfo::trajectory` holds the sampled history of current iteration
+pub trait Fitness: Send + Sync {
+    /// name of the score, used in logs
+    fn name(&self) -> &'static str;
+
+    fn score(&self, geno: &BlobGeno, info: &BlobInfo) -> f32;
+
+    /// score of the task itself, without wrappers like `EfficientFitness`
+    fn task_score(&self, geno: &BlobGeno, info: &BlobInfo) -> f32 {
+        self.score(geno, info)
+    }
+}
+
+/// moving distance in any direction
+pub struct SwimFitness;
+
+impl Fitness for SwimFitness {
+    fn name(&self) -> &'static str {
+        "distance"
+    }
+
+    fn score(&self, _geno: &BlobGeno, info: &BlobInfo) -> f32 {
+        info.move_distance
+            .iter()
+            .fold(0.0, |acc, &x| acc + x * x)
+            .sqrt()
+    }
+}
+
+/// moving distance on x axis
+pub struct WalkFitness;
+
+impl Fitness for WalkFitness {
+    fn name(&self) -> &'static str {
+        "x_distance"
+    }
+
+    fn score(&self, _geno: &BlobGeno, info: &BlobInfo) -> f32 {
+        info.move_distance[0]
+    }
+}
+
+/// food eaten
+pub struct ForageFitness;
+
+impl Fitness for ForageFitness {
+    fn name(&self) -> &'static str {
+        "food"
+    }
+
+    fn score(&self, _geno: &BlobGeno, info: &BlobInfo) -> f32 {
+        info.food_collected as f32
+    }
+}
+
+/// score per energy spent, see `EnergyConfig::efficiency_fitness`.
+///
+/// A negative score is multiplied instead, so spending more energy never helps
+pub fn per_energy(score: f32, energy_used: f32) -> f32 {
+    let cost = 1.0 + energy_used.max(0.0);
+    if score < 0.0 {
+        score * cost
+    } else {
+        score / cost
+    }
+}
+
+/// another fitness divided by the energy spent
+pub struct EfficientFitness(pub Box<dyn Fitness>);
+
+impl Fitness for EfficientFitness {
+    fn name(&self) -> &'static str {
+        "efficiency"
+    }
+
+    fn score(&self, geno: &BlobGeno, info: &BlobInfo) -> f32 {
+        per_energy(self.0.score(geno, info), info.energy_used)
+    }
+
+    fn task_score(&self, geno: &BlobGeno, info: &BlobInfo) -> f32 {
+        self.0.task_score(geno, info)
+    }
+}
+
+/// Bevy resource holding the fitness function of current training
+#[derive(Resource)]
+pub struct TrainFitness(pub Box<dyn Fitness>);
+
+impl TrainFitness {
+    pub fn from_mode(mode: TrainingMode) -> Self {
+        match mode {
+            TrainingMode::Swim => Self(Box::new(SwimFitness)),
+            TrainingMode::Walk => Self(Box::new(WalkFitness)),
+            TrainingMode::Forage => Self(Box::new(ForageFitness)),
+        }
+    }
+
+    /// divide the score by the energy spent
+    pub fn per_energy(self) -> Self {
+        Self(Box::new(EfficientFitness(self.0)))
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.0.name()
+    }
+
+    pub fn score(&self, geno: &BlobGeno, info: &BlobInfo) -> f32 {
+        self.0.score(geno, info)
+    }
+
+    pub fn task_score(&self, geno: &BlobGeno, info: &BlobInfo) -> f32 {
+        self.0.task_score(geno, info)
+    }
+}
+
+#[cfg(test)]
+mod fitness_test {
+    use super::*;
+
+    #[test]
+    fn test_per_energy() {
+        assert_eq!(per_energy(10.0, 4.0), 2.0);
+        assert_eq!(per_energy(-10.0, 4.0), -50.0);
+        // spending more energy is worse for any score
+        for score in [-1.0, 0.0, 1.0] {
+            assert!(per_energy(score, 1.0) >= per_energy(score, 2.0));
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! hall of fame, the best blobs ever seen during training
+
+use std::{error::Error, fs, path::Path};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    blob::geno_blob_builder::BlobGeno,
+    brain::{neat::Innovations, neuron::GenericNN},
+    config::{config, IOConfig},
+    consts::HALL_OF_FAME_FNAME,
+    logger_info,
+};
+
+/// a blob in the hall of fame, nn_id of `geno` index `nnvec`
+#[derive(Serialize, Deserialize)]
+pub struct HallOfFameEntry {
+    pub fitness: f32,
+    /// generation the blob was evaluated in
+    pub generation: u128,
+    pub geno: BlobGeno,
+    pub nnvec: Vec<GenericNN>,
+}
+
+/// Bevy resource keeping the best blobs across generations, best first
+#[derive(Resource, Default, Serialize, Deserialize)]
+pub struct HallOfFame {
+    pub entries: Vec<HallOfFameEntry>,
+    /// generations seen until the last update, including the runs it was loaded from
+    #[serde(default)]
+    pub generations: u128,
+    /// `generations` when loaded, generations of a resumed run count on from it
+    #[serde(skip)]
+    offset: u128,
+}
+
+impl HallOfFame {
+    /// load the hall of fame next to `io.load_fname` if `io.load_on_start` is set,
+    /// otherwise start with an empty one
+    pub fn from_config(io: &IOConfig, innovations: &mut Innovations) -> Self {
+        if !io.load_on_start {
+            return Self::default();
+        }
+        let fname = io.load_fname_folder().join(HALL_OF_FAME_FNAME);
+        if !fname.exists() {
+            return Self::default();
+        }
+        match Self::load(&fname.to_string_lossy(), innovations) {
+            Ok(hall_of_fame) => {
+                logger_info!(
+                    "HALL OF FAME LOADED {}, {} entries",
+                    fname.display(),
+                    hall_of_fame.entries.len()
+                );
+                hall_of_fame
+            }
+            Err(e) => {
+                warn!("Failed to load {}: {}", fname.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    /// add blobs that are better than the entries, and keep the best `size`.
+    ///
+    /// A blob already in the hall of fame (same lineage id) has one entry,
+    /// with its best fitness and the generation it first entered.
+    ///
+    /// `genovec` and `scores` are paired, nn_id of genos index `nnvec`.
+    ///
+    /// return `true` if hall of fame changed
+    pub fn update(
+        &mut self,
+        genovec: &[&BlobGeno],
+        scores: &[f32],
+        nnvec: &[GenericNN],
+        generation: u128,
+        size: usize,
+    ) -> bool {
+        let generation = self.offset + generation;
+        self.generations = self.generations.max(generation + 1);
+        let mut changed = false;
+        for (&geno, &fitness) in genovec.iter().zip(scores.iter()) {
+            let mut first_generation = generation;
+            let same = geno.lineage.id.and_then(|id| {
+                self.entries
+                    .iter()
+                    .position(|e| e.geno.lineage.id == Some(id))
+            });
+            if let Some(idx) = same {
+                if self.entries[idx].fitness >= fitness {
+                    continue;
+                }
+                first_generation = self.entries.remove(idx).generation;
+                changed = true;
+            }
+
+            let is_full = self.entries.len() >= size;
+            let worst = self.entries.last().map_or(f32::NEG_INFINITY, |e| e.fitness);
+            if is_full && fitness <= worst {
+                continue;
+            }
+
+            let (geno, nnvec) = geno.detach_nn(nnvec);
+            let idx = self.entries.partition_point(|e| e.fitness >= fitness);
+            self.entries.insert(
+                idx,
+                HallOfFameEntry {
+                    fitness,
+                    generation: first_generation,
+                    geno,
+                    nnvec,
+                },
+            );
+            self.entries.truncate(size);
+            changed = true;
+        }
+        changed
+    }
+
+    /// save to `HALL_OF_FAME_FNAME` in the folder, overwrite the old one
+    pub fn save(&self, folder: &str) {
+        if let Err(e) = fs::create_dir_all(folder) {
+            warn!("Failed to create folder {}: {}", folder, e);
+            return;
+        }
+        let fname = Path::new(folder).join(HALL_OF_FAME_FNAME);
+        let file_str = serde_json::to_string(&self).unwrap();
+        match fs::write(&fname, file_str) {
+            Ok(_) => {
+                logger_info!(
+                    "HALL OF FAME SAVED {}, best fitness {:.5}",
+                    fname.display(),
+                    self.entries.first().map_or(f32::NAN, |e| e.fitness)
+                );
+            }
+            Err(e) => warn!("Failed to save {}: {}", fname.display(), e),
+        }
+    }
+
+    /// NNs saved with other shapes are migrated to the configured ones
+    pub fn load(path: &str, innovations: &mut Innovations) -> Result<Self, Box<dyn Error>> {
+        let file_str = fs::read_to_string(path)?;
+        let mut hall_of_fame = serde_json::from_str::<HallOfFame>(&file_str)?;
+        for nn in hall_of_fame.entries.iter_mut().flat_map(|e| e.nnvec.iter_mut()) {
+            nn.migrate(config());
+            nn.observe_innovations(innovations);
+        }
+        hall_of_fame.offset = hall_of_fame.generations;
+        Ok(hall_of_fame)
+    }
+}
+
+#[cfg(test)]
+mod hall_of_fame_test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::brain::neuron::BrainNN;
+
+    /// random geno with lineage `id`, and the NNs its nn_id index
+    fn geno_with_nn(id: u64, rng: &mut StdRng) -> (BlobGeno, Vec<GenericNN>) {
+        let mut geno = BlobGeno::new_rand(rng);
+        let mut nnvec = Vec::<GenericNN>::new();
+        for nn_id in geno.all_nn_ids_mut() {
+            *nn_id = Some(nnvec.len());
+            nnvec.push(GenericNN::BRAINNN(BrainNN::new_rand(&mut Innovations::default(), rng)));
+        }
+        geno.lineage.id = Some(id);
+        (geno, nnvec)
+    }
+
+    #[test]
+    fn test_no_duplicates() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (geno, nnvec) = geno_with_nn(7, &mut rng);
+        let mut other = geno.clone();
+        other.lineage.id = Some(8);
+
+        let mut hof = HallOfFame::default();
+        assert!(hof.update(&[&geno, &other], &[2.0, 1.0], &nnvec, 0, 3));
+        // an elite scored again is not added twice
+        assert!(!hof.update(&[&geno], &[1.5], &nnvec, 1, 3));
+        assert!(hof.update(&[&geno], &[3.0], &nnvec, 2, 3));
+        assert_eq!(hof.entries.len(), 2);
+        assert_eq!(hof.entries[0].fitness, 3.0);
+        assert_eq!(hof.entries[0].generation, 0);
+        assert_eq!(hof.entries[1].geno.lineage.id, Some(8));
+    }
+
+    #[test]
+    fn test_save_load() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (geno, nnvec) = geno_with_nn(7, &mut rng);
+        let mut hof = HallOfFame::default();
+        hof.update(&[&geno], &[2.0], &nnvec, 4, 3);
+
+        let folder = std::env::temp_dir().join(format!("evosim_hof_{}", std::process::id()));
+        hof.save(&folder.to_string_lossy());
+        let fname = folder.join(HALL_OF_FAME_FNAME);
+        let mut innovations = Innovations::default();
+        let mut loaded = HallOfFame::load(&fname.to_string_lossy(), &mut innovations).unwrap();
+        fs::remove_dir_all(&folder).unwrap();
+
+        assert_eq!(loaded.entries.len(), 1);
+        assert_eq!(loaded.entries[0].fitness, 2.0);
+        assert_eq!(loaded.entries[0].geno.lineage.id, Some(7));
+        assert_eq!(loaded.entries[0].nnvec.len(), hof.entries[0].nnvec.len());
+        // the resumed run continues the generations
+        let (other, nnvec) = geno_with_nn(8, &mut rng);
+        assert!(loaded.update(&[&other], &[3.0], &nnvec, 0, 3));
+        assert_eq!(loaded.entries[0].generation, 5);
+        assert_eq!(loaded.entries[1].generation, 4);
+    }
+}
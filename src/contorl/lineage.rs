@@ -0,0 +1,84 @@
+//! track the lineage of blobs during training, see `io/lineage.rs` for the file format
+
+use std::path::{Path, PathBuf};
+
+use bevy::prelude::*;
+
+use crate::{
+    blob::geno_blob_builder::BlobGeno,
+    consts::LINEAGE_FNAME,
+    io::lineage::{read_records, write_records, LineageRecord},
+};
+
+/// Bevy resource assigning blob ids and writing the lineage file
+#[derive(Resource)]
+pub struct LineageTracker {
+    next_id: u64,
+    path: PathBuf,
+    /// keep the records of the loaded run
+    append: bool,
+}
+
+impl LineageTracker {
+    /// the lineage file is in `folder`,
+    /// the old file is overwritten by the first record unless `append` is set.
+    ///
+    /// When appending, ids continue after the ones in the old file,
+    /// so that new blobs don't reuse ids of dead blobs
+    pub fn new(folder: &str, append: bool) -> Self {
+        let path = Path::new(folder).join(LINEAGE_FNAME);
+        let mut next_id = 0;
+        if append && path.exists() {
+            match read_records(&path.to_string_lossy()) {
+                Ok(records) => {
+                    next_id = records
+                        .iter()
+                        .flat_map(|r| r.parents.iter().chain(std::iter::once(&r.id)))
+                        .map(|&id| id + 1)
+                        .max()
+                        .unwrap_or(0)
+                }
+                Err(e) => warn!("Failed to read {}: {}", path.display(), e),
+            }
+        }
+        Self {
+            next_id,
+            path,
+            append,
+        }
+    }
+
+    /// assign ids to new blobs, and record the generation in the lineage file.
+    ///
+    /// Mutations are moved from the genos into the records.
+    /// `genovec` and `scores` are paired
+    pub fn record(&mut self, generation: u128, genovec: Vec<&mut BlobGeno>, scores: &[f32]) {
+        // blobs loaded from file already have ids
+        for geno in genovec.iter() {
+            if let Some(id) = geno.lineage.id {
+                self.next_id = self.next_id.max(id + 1);
+            }
+        }
+
+        let mut records = Vec::<LineageRecord>::new();
+        for (geno, &fitness) in genovec.into_iter().zip(scores.iter()) {
+            let lineage = &mut geno.lineage;
+            let id = *lineage.id.get_or_insert_with(|| {
+                self.next_id += 1;
+                self.next_id - 1
+            });
+            records.push(LineageRecord {
+                generation,
+                id,
+                parents: lineage.parents.clone(),
+                fitness,
+                mutations: std::mem::take(&mut lineage.mutations),
+            });
+        }
+
+        if let Err(e) = write_records(&self.path, &records, !self.append) {
+            warn!("Failed to write {}: {}", self.path.display(), e);
+        }
+        self.append = true;
+    }
+}
@@ -0,0 +1,301 @@
+//! MAP-Elites, an archive grid over morphology features.
+//!
+//! Each cell keeps the best-moving blob of its niche,
+//! parents are drawn from the archive, so training ends with a catalogue of different creatures.
+
+use std::{error::Error, fs, path::Path};
+
+use bevy::prelude::*;
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    blob::geno_blob_builder::{BlobGeno, GenericGenoNode},
+    brain::{neat::Innovations, neuron::GenericNN},
+    config::{config, GridAxis, IOConfig, MapElitesConfig},
+    consts::MAP_ELITES_FNAME,
+    logger_info,
+};
+
+/// a niche of the grid, nn_id of `geno` index `nnvec`
+#[derive(Serialize, Deserialize)]
+pub struct MapElitesCell {
+    /// bin of each feature
+    pub index: [usize; 4],
+    /// block count, tree depth, limb symmetry, total area
+    pub features: [f32; 4],
+    pub fitness: f32,
+    /// generation the blob was evaluated in
+    pub generation: u128,
+    pub geno: BlobGeno,
+    pub nnvec: Vec<GenericNN>,
+}
+
+/// Bevy resource of the MAP-Elites archive, only filled cells are stored
+#[derive(Resource, Default, Serialize, Deserialize)]
+pub struct MapElites {
+    pub cells: Vec<MapElitesCell>,
+}
+
+impl MapElites {
+    /// load the archive next to `io.load_fname` if `io.load_on_start` is set,
+    /// otherwise start with an empty one
+    pub fn from_config(io: &IOConfig, innovations: &mut Innovations) -> Self {
+        if !io.load_on_start {
+            return Self::default();
+        }
+        // the population is loaded from `load_fname`, the archive of that run is beside it
+        let fname = io.load_fname_folder().join(MAP_ELITES_FNAME);
+        if !fname.exists() {
+            return Self::default();
+        }
+        match Self::load(&fname.to_string_lossy(), innovations) {
+            Ok(map_elites) => {
+                logger_info!(
+                    "MAP-ELITES LOADED {}, {} cells",
+                    fname.display(),
+                    map_elites.cells.len()
+                );
+                map_elites
+            }
+            Err(e) => {
+                warn!("Failed to load {}: {}", fname.display(), e);
+                Self::default()
+            }
+        }
+    }
+
+    /// put blobs into their cells if the cell is empty or they move better.
+    ///
+    /// `genovec` and `scores` are paired, nn_id of genos index `nnvec`.
+    ///
+    /// return `true` if the archive changed
+    pub fn insert(
+        &mut self,
+        genovec: &[&BlobGeno],
+        scores: &[f32],
+        nnvec: &[GenericNN],
+        generation: u128,
+        config: &MapElitesConfig,
+    ) -> bool {
+        let mut changed = false;
+        for (&geno, &fitness) in genovec.iter().zip(scores.iter()) {
+            let features = features(geno);
+            let index = cell_index(&features, config);
+            let pos = self.cells.iter().position(|c| c.index == index);
+            if let Some(pos) = pos {
+                if self.cells[pos].fitness >= fitness {
+                    continue;
+                }
+            }
+
+            let (geno, nnvec) = geno.detach_nn(nnvec);
+            let cell = MapElitesCell {
+                index,
+                features,
+                fitness,
+                generation,
+                geno,
+                nnvec,
+            };
+            match pos {
+                Some(pos) => self.cells[pos] = cell,
+                None => self.cells.push(cell),
+            }
+            changed = true;
+        }
+        changed
+    }
+
+    /// draw `n` parents from uniformly random cells.
+    ///
+    /// return the genos and the nnvec their nn_id index
+    pub fn sample(&self, n: usize, rng: &mut impl Rng) -> (Vec<BlobGeno>, Vec<GenericNN>) {
+        let mut genovec = Vec::<BlobGeno>::new();
+        let mut nnvec = Vec::<GenericNN>::new();
+        if self.cells.is_empty() {
+            return (genovec, nnvec);
+        }
+        for _ in 0..n {
+            let cell = self.cells.choose(rng).unwrap();
+            let mut geno = cell.geno.clone();
+            let offset = nnvec.len();
+            for nn_id in geno.all_nn_ids_mut() {
+                if let Some(id) = nn_id {
+                    *id += offset;
+                }
+            }
+            nnvec.extend(cell.nnvec.iter().cloned());
+            genovec.push(geno);
+        }
+        (genovec, nnvec)
+    }
+
+    /// log coverage and fitness of the archive
+    pub fn log(&self, iteration: u128, fitness_name: &str, config: &MapElitesConfig) {
+        let total = config.block_count.bins
+            * config.depth.bins
+            * config.symmetry.bins
+            * config.area.bins;
+        let top = self
+            .cells
+            .iter()
+            .map(|c| c.fitness)
+            .fold(f32::NEG_INFINITY, f32::max);
+        let qd_score: f32 = self.cells.iter().map(|c| c.fitness).sum();
+        logger_info!(
+            "iteration {}, map_elites {}/{} cells, top_{} {:.5}, qd_score {:.5}",
+            iteration,
+            self.cells.len(),
+            total,
+            fitness_name,
+            top,
+            qd_score
+        );
+    }
+
+    /// save to `MAP_ELITES_FNAME` in the folder, overwrite the old one
+    pub fn save(&self, folder: &str) {
+        if let Err(e) = fs::create_dir_all(folder) {
+            warn!("Failed to create folder {}: {}", folder, e);
+            return;
+        }
+        let fname = Path::new(folder).join(MAP_ELITES_FNAME);
+        let file_str = serde_json::to_string(&self).unwrap();
+        match fs::write(&fname, file_str) {
+            Ok(_) => {
+                logger_info!("MAP-ELITES SAVED {}, {} cells", fname.display(), self.cells.len());
+            }
+            Err(e) => warn!("Failed to save {}: {}", fname.display(), e),
+        }
+    }
+
+    /// NNs saved with other shapes are migrated to the configured ones
+    pub fn load(path: &str, innovations: &mut Innovations) -> Result<Self, Box<dyn Error>> {
+        let file_str = fs::read_to_string(path)?;
+        let mut map_elites = serde_json::from_str::<MapElites>(&file_str)?;
+        for nn in map_elites.cells.iter_mut().flat_map(|cell| cell.nnvec.iter_mut()) {
+            nn.migrate(config());
+            nn.observe_innovations(innovations);
+        }
+        Ok(map_elites)
+    }
+}
+
+/// morphology features of the geno: block count, tree depth, limb symmetry, total area.
+///
+/// Symmetry is the ratio of limbs whose left-right mirror also exists, `1.0` without limbs
+pub fn features(geno: &BlobGeno) -> [f32; 4] {
+    let tree = &geno.vec_tree;
+    let blocks = geno.all_nn_ids_indices();
+
+    let mut depth = 0;
+    let mut area = 0.0;
+    let mut limbs = 0;
+    let mut mirrored = 0;
+    for &idx in blocks.iter() {
+        if let Some(GenericGenoNode::Child(node)) = &tree.nodes[idx] {
+            area += 4.0 * node.size[0] * node.size[1];
+        }
+        let mut d = 0;
+        let mut cur = idx;
+        while let Some(parent) = tree.parent(cur) {
+            cur = parent;
+            d += 1;
+        }
+        depth = depth.max(d);
+
+        if idx != 0 {
+            limbs += 1;
+            let mirror = mirror_index(idx);
+            if matches!(tree.nodes.get(mirror), Some(Some(GenericGenoNode::Child(_)))) {
+                mirrored += 1;
+            }
+        }
+    }
+    let symmetry = if limbs == 0 {
+        1.0
+    } else {
+        mirrored as f32 / limbs as f32
+    };
+
+    [blocks.len() as f32, depth as f32, symmetry, area]
+}
+
+/// bin of each feature in the grid
+pub fn cell_index(features: &[f32; 4], config: &MapElitesConfig) -> [usize; 4] {
+    [
+        config.block_count.bin(features[0]),
+        config.depth.bin(features[1]),
+        config.symmetry.bin(features[2]),
+        config.area.bin(features[3]),
+    ]
+}
+
+impl GridAxis {
+    /// values out of `[min, max)` fall into the first or last bin
+    pub fn bin(&self, value: f32) -> usize {
+        let ratio = (value - self.min) / (self.max - self.min);
+        ((ratio * self.bins as f32).floor().max(0.0) as usize).min(self.bins.max(1) - 1)
+    }
+}
+
+/// index of the node at the mirrored position, left and right swapped
+fn mirror_index(idx: usize) -> usize {
+    if idx == 0 {
+        return 0;
+    }
+    let parent = (idx - 1) / 4;
+    let direction = match (idx - 1) % 4 {
+        2 => 3,
+        3 => 2,
+        d => d,
+    };
+    4 * mirror_index(parent) + 1 + direction
+}
+
+#[cfg(test)]
+mod map_elites_test {
+    use rand::rngs::StdRng;
+
+    use super::*;
+    use crate::brain::neuron::BrainNN;
+
+    #[test]
+    fn test_map_elites_keep_best() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let config = MapElitesConfig::default();
+        let mut geno = BlobGeno::new_rand(&mut rng);
+        let mut innovations = Innovations::default();
+        let mut nnvec = Vec::<GenericNN>::new();
+        for nn_id in geno.all_nn_ids_mut() {
+            *nn_id = Some(nnvec.len());
+            nnvec.push(GenericNN::BRAINNN(BrainNN::new_rand(&mut innovations, &mut rng)));
+        }
+
+        let mut map_elites = MapElites::default();
+        assert!(map_elites.insert(&[&geno], &[1.0], &nnvec, 0, &config));
+        assert!(!map_elites.insert(&[&geno], &[0.5], &nnvec, 1, &config));
+        assert!(map_elites.insert(&[&geno], &[2.0], &nnvec, 2, &config));
+        assert_eq!(map_elites.cells.len(), 1);
+        assert_eq!(map_elites.cells[0].fitness, 2.0);
+
+        let (parents, parent_nnvec) = map_elites.sample(3, &mut rng);
+        assert_eq!(parents.len(), 3);
+        assert_eq!(parent_nnvec.len(), 3 * nnvec.len());
+        assert!(parents[2]
+            .all_usize_nn_ids()
+            .iter()
+            .all(|&id| id < parent_nnvec.len()));
+    }
+
+    #[test]
+    fn test_mirror_index() {
+        // left and right children of the root
+        assert_eq!(mirror_index(3), 4);
+        assert_eq!(mirror_index(4), 3);
+        assert_eq!(mirror_index(1), 1);
+        // left child of the up child
+        assert_eq!(mirror_index(7), 8);
+    }
+}
@@ -3,4 +3,13 @@
 pub mod update;
 pub mod contorl;
 pub mod train_move;
+pub mod fitness;
+pub mod selection;
+pub mod species;
+pub mod hall_of_fame;
+pub mod lineage;
+pub mod map_elites;
+pub mod novelty;
+pub mod touch;
+pub mod vision;
 pub mod resource;
\ No newline at end of file
@@ -0,0 +1,153 @@
+//! novelty search, score blobs by how different their behaviour is.
+//!
+//! Displacement alone makes the population converge on one gait,
+//! novelty rewards behaviours far from the population and an archive of past behaviours.
+
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::{blob::blob::BlobInfo, config::NoveltyConfig, logger_info};
+
+/// behaviour descriptor of a blob at the end of an iteration:
+/// - sampled `mass_center` trajectory, relative to the start
+/// - final position, relative to the start
+/// - mean and std of joint angles, mean absolute joint velocity, scaled by `joint_weight`
+pub fn behaviour_descriptor(info: &BlobInfo, config: &NoveltyConfig) -> Vec<f32> {
+    let mut descriptor = Vec::<f32>::new();
+    let trajectory = &info.trajectory;
+    let start = trajectory.first().copied().unwrap_or(info.mass_center);
+    let end = trajectory.last().copied().unwrap_or(info.mass_center);
+
+    for i in 0..config.trajectory_samples {
+        let point = if trajectory.is_empty() {
+            start
+        } else {
+            trajectory[(i + 1) * (trajectory.len() - 1) / config.trajectory_samples]
+        };
+        descriptor.push(point[0] - start[0]);
+        descriptor.push(point[1] - start[1]);
+    }
+    descriptor.push(end[0] - start[0]);
+    descriptor.push(end[1] - start[1]);
+
+    for stat in info.joint_stats.stats() {
+        descriptor.push(stat * config.joint_weight);
+    }
+    descriptor
+}
+
+/// Bevy resource of past behaviour descriptors, oldest first
+#[derive(Resource, Default)]
+pub struct NoveltyArchive {
+    pub descriptors: VecDeque<Vec<f32>>,
+}
+
+impl NoveltyArchive {
+    /// novelty of each descriptor: mean distance to the `k` nearest neighbours
+    /// in the rest of the population and the archive.
+    ///
+    /// The most novel `archive_add` descriptors are added to the archive afterwards.
+    pub fn evaluate(&mut self, descriptors: &[Vec<f32>], config: &NoveltyConfig) -> Vec<f32> {
+        let novelty: Vec<f32> = descriptors
+            .iter()
+            .enumerate()
+            .map(|(i, descriptor)| {
+                let mut distances: Vec<f32> = descriptors
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, other)| distance(descriptor, other))
+                    .chain(self.descriptors.iter().map(|other| distance(descriptor, other)))
+                    .collect();
+                distances.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+                let k = config.k.min(distances.len());
+                if k == 0 {
+                    return 0.0;
+                }
+                distances[..k].iter().sum::<f32>() / k as f32
+            })
+            .collect();
+
+        let mut order: Vec<usize> = (0..novelty.len()).collect();
+        order.sort_by(|&a, &b| {
+            novelty[b]
+                .partial_cmp(&novelty[a])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        for &i in order.iter().take(config.archive_add) {
+            self.descriptors.push_back(descriptors[i].clone());
+        }
+        while self.descriptors.len() > config.archive_size {
+            self.descriptors.pop_front();
+        }
+
+        novelty
+    }
+
+    /// log novelty stats
+    pub fn log(&self, iteration: u128, novelty: &[f32]) {
+        let top = novelty.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        let mean = novelty.iter().sum::<f32>() / novelty.len().max(1) as f32;
+        logger_info!(
+            "iteration {}, top_novelty {:.5}, mean_novelty {:.5}, archive {}",
+            iteration,
+            top,
+            mean,
+            self.descriptors.len()
+        );
+    }
+}
+
+/// blend fitness and novelty, both are normalized to `[0, 1]` in the population first.
+///
+/// `weight == 1.0` is novelty alone
+pub fn blend(fitness: &[f32], novelty: &[f32], weight: f32) -> Vec<f32> {
+    let fitness = normalize(fitness);
+    let novelty = normalize(novelty);
+    fitness
+        .iter()
+        .zip(novelty.iter())
+        .map(|(f, n)| (1.0 - weight) * f + weight * n)
+        .collect()
+}
+
+fn normalize(values: &[f32]) -> Vec<f32> {
+    let min = values.iter().cloned().fold(f32::INFINITY, f32::min);
+    let max = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    if max - min <= f32::EPSILON {
+        return vec![0.0; values.len()];
+    }
+    values.iter().map(|v| (v - min) / (max - min)).collect()
+}
+
+fn distance(a: &[f32], b: &[f32]) -> f32 {
+    a.iter()
+        .zip(b.iter())
+        .fold(0.0, |acc, (x, y)| acc + (x - y) * (x - y))
+        .sqrt()
+}
+
+#[cfg(test)]
+mod novelty_test {
+    use super::*;
+
+    #[test]
+    fn test_novelty_outlier() {
+        let config = NoveltyConfig {
+            k: 2,
+            archive_add: 1,
+            archive_size: 1,
+            ..Default::default()
+        };
+        let descriptors = vec![vec![0.0, 0.0], vec![1.0, 0.0], vec![0.0, 1.0], vec![10.0, 10.0]];
+        let mut archive = NoveltyArchive::default();
+        let novelty = archive.evaluate(&descriptors, &config);
+        assert!(novelty[3] > novelty[0] && novelty[3] > novelty[1] && novelty[3] > novelty[2]);
+        assert_eq!(archive.descriptors, vec![vec![10.0, 10.0]]);
+
+        // novelty alone ignores fitness
+        let scores = blend(&[5.0, 4.0, 3.0, 0.0], &novelty, 1.0);
+        assert_eq!(scores[3], 1.0);
+    }
+}
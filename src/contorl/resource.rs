@@ -35,11 +35,14 @@ impl Default for TED {
 /// 
 /// When doing cleaning, this Resource stores 
 /// all the blobs and neurons waiting to be spawned
+/// 
+/// the first `elite` blobs are elites, which skip mutation
 #[derive(Resource)]
 pub struct TrainMutPipe {
     genovec: Vec<BlobGeno>,
     infovec: Vec<BlobInfo>,
     nnvec: Vec<GenericNN>,
+    elite: usize,
 }
 
 impl Default for TrainMutPipe {
@@ -48,32 +51,43 @@ impl Default for TrainMutPipe {
             genovec: Vec::<BlobGeno>::new(),
             infovec: Vec::<BlobInfo>::new(),
             nnvec: Vec::<GenericNN>::new(),
+            elite: 0,
         }
     }
 }
 
 impl TrainMutPipe {
-    pub fn push(&mut self, genovec: Vec<BlobGeno>, infovec: Vec<BlobInfo>, nnvec: Vec<GenericNN>) {
+    pub fn push(
+        &mut self,
+        genovec: Vec<BlobGeno>,
+        infovec: Vec<BlobInfo>,
+        nnvec: Vec<GenericNN>,
+        elite: usize,
+    ) {
         assert!(self.genovec.is_empty());
         assert!(self.infovec.is_empty());
         assert!(self.nnvec.is_empty());
+        assert!(elite <= genovec.len());
         self.genovec = genovec;
         self.infovec = infovec;
         self.nnvec = nnvec;
+        self.elite = elite;
         assert!(!self.genovec.is_empty());
         assert!(!self.infovec.is_empty());
         assert!(!self.nnvec.is_empty());
     }
 
-    pub fn pop(&mut self) -> (Vec<BlobGeno>, Vec<BlobInfo>, Vec<GenericNN>) {
+    pub fn pop(&mut self) -> (Vec<BlobGeno>, Vec<BlobInfo>, Vec<GenericNN>, usize) {
         assert!(!self.genovec.is_empty());
         assert!(!self.infovec.is_empty());
         assert!(!self.nnvec.is_empty());
-        let res: (Vec<BlobGeno>, Vec<BlobInfo>, Vec<GenericNN>) = (
+        let res: (Vec<BlobGeno>, Vec<BlobInfo>, Vec<GenericNN>, usize) = (
             self.genovec.clone(),
             self.infovec.clone(),
             self.nnvec.clone(),
+            self.elite,
         );
+        self.elite = 0;
         self.genovec.clear();
         self.infovec.clear();
         self.nnvec.clear();
@@ -0,0 +1,184 @@
+//! multi-objective selection, NSGA-II style
+//!
+//! blobs are sorted into Pareto fronts by non-dominated sorting,
+//! the last front that does not fit is cut by crowding distance in objective space.
+
+use serde::{Deserialize, Serialize};
+
+use crate::blob::{blob::BlobInfo, geno_blob_builder::BlobGeno};
+
+use super::fitness::{per_energy, TrainFitness};
+
+/// how survivers are selected in training
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Selection {
+    /// sort by fitness, then swap some survivers with TED-ranked blobs
+    Hybrid,
+    /// non-dominated sorting over `objectives`
+    Nsga2,
+    /// draw parents from the MAP-Elites archive over morphology features
+    MapElites,
+}
+
+/// objectives for `Selection::Nsga2`, all of them are maximized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Objective {
+    /// score of `TrainFitness` (shared if speciation is enabled)
+    Fitness,
+    /// `BlobInfo::crowding_distance`, the diversity in morphology
+    Crowding,
+    /// fewer blocks is better
+    BlockCount,
+    /// fitness score per energy spent, see `EnergyConfig`
+    Efficiency,
+}
+
+impl Objective {
+    /// `score` is the fitness score of the blob,
+    /// `Efficiency` uses the task score of `fitness` to not divide twice
+    pub fn value(
+        &self,
+        score: f32,
+        fitness: &TrainFitness,
+        geno: &BlobGeno,
+        info: &BlobInfo,
+    ) -> f32 {
+        match self {
+            Objective::Fitness => score,
+            Objective::Crowding => info.crowding_distance,
+            Objective::BlockCount => -(geno.all_nn_ids_indices().len() as f32),
+            Objective::Efficiency => per_energy(fitness.task_score(geno, info), info.energy_used),
+        }
+    }
+}
+
+/// select `n` blobs base on their objective values.
+///
+/// return the selected indices and the indices of the first Pareto front
+pub fn nsga2_select(objectives: &[Vec<f32>], n: usize) -> (Vec<usize>, Vec<usize>) {
+    let fronts = non_dominated_sort(objectives);
+    let mut selected = Vec::<usize>::new();
+
+    for front in fronts.iter() {
+        if selected.len() + front.len() <= n {
+            selected.extend(front);
+            continue;
+        }
+        // cut the front, keep the less crowded ones
+        let distances = crowding_distance(objectives, front);
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        order.sort_by(|&a, &b| {
+            distances[b]
+                .partial_cmp(&distances[a])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        selected.extend(order.iter().take(n - selected.len()).map(|&i| front[i]));
+        break;
+    }
+
+    (selected, fronts.into_iter().next().unwrap_or_default())
+}
+
+/// sort indices into Pareto fronts, best front first
+pub fn non_dominated_sort(objectives: &[Vec<f32>]) -> Vec<Vec<usize>> {
+    let len = objectives.len();
+    // who `i` dominates, and how many dominate `i`
+    let mut dominated = vec![Vec::<usize>::new(); len];
+    let mut domination_count = vec![0usize; len];
+
+    for i in 0..len {
+        for j in 0..len {
+            if dominates(&objectives[i], &objectives[j]) {
+                dominated[i].push(j);
+            } else if dominates(&objectives[j], &objectives[i]) {
+                domination_count[i] += 1;
+            }
+        }
+    }
+
+    let mut fronts = Vec::<Vec<usize>>::new();
+    let mut current: Vec<usize> = (0..len).filter(|&i| domination_count[i] == 0).collect();
+    while !current.is_empty() {
+        let mut next = Vec::<usize>::new();
+        for &i in current.iter() {
+            for &j in dominated[i].iter() {
+                domination_count[j] -= 1;
+                if domination_count[j] == 0 {
+                    next.push(j);
+                }
+            }
+        }
+        fronts.push(current);
+        current = next;
+    }
+    fronts
+}
+
+/// `a` is not worse than `b` in all objectives, and better in at least one
+fn dominates(a: &[f32], b: &[f32]) -> bool {
+    a.iter().zip(b.iter()).all(|(x, y)| x >= y) && a.iter().zip(b.iter()).any(|(x, y)| x > y)
+}
+
+/// crowding distance in objective space for each blob in the front.
+///
+/// Not to confuse with `BlobInfo::crowding_distance`, which is measured by TED.
+/// Boundary blobs have infinite distance.
+fn crowding_distance(objectives: &[Vec<f32>], front: &[usize]) -> Vec<f32> {
+    let mut distances = vec![0.0f32; front.len()];
+    if front.len() <= 2 {
+        return vec![f32::INFINITY; front.len()];
+    }
+
+    let obj_len = objectives[front[0]].len();
+    for m in 0..obj_len {
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        order.sort_by(|&a, &b| {
+            objectives[front[a]][m]
+                .partial_cmp(&objectives[front[b]][m])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let min = objectives[front[order[0]]][m];
+        let max = objectives[front[order[front.len() - 1]]][m];
+        distances[order[0]] = f32::INFINITY;
+        distances[order[front.len() - 1]] = f32::INFINITY;
+        if max - min <= f32::EPSILON {
+            continue;
+        }
+
+        for k in 1..front.len() - 1 {
+            let prev = objectives[front[order[k - 1]]][m];
+            let next = objectives[front[order[k + 1]]][m];
+            distances[order[k]] += (next - prev) / (max - min);
+        }
+    }
+    distances
+}
+
+#[cfg(test)]
+mod selection_test {
+    use super::*;
+
+    #[test]
+    fn test_non_dominated_sort() {
+        let objectives = vec![
+            vec![1.0, 1.0],
+            vec![3.0, 1.0],
+            vec![1.0, 3.0],
+            vec![2.0, 2.0],
+            vec![0.0, 0.0],
+        ];
+        let fronts = non_dominated_sort(&objectives);
+        assert_eq!(fronts[0], vec![1, 2, 3]);
+        assert_eq!(fronts[1], vec![0]);
+        assert_eq!(fronts[2], vec![4]);
+
+        // boundary blobs of the first front are kept
+        let (selected, front) = nsga2_select(&objectives, 2);
+        assert_eq!(front, vec![1, 2, 3]);
+        assert_eq!(selected.len(), 2);
+        assert!(selected.contains(&1) && selected.contains(&2));
+    }
+}
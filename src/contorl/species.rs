@@ -0,0 +1,362 @@
+//! speciation, cluster the population into species and share fitness inside each species.
+//!
+//! New morphologies introduced by mutation usually perform bad before their NN is tuned,
+//! sharing fitness inside species protects them from being eliminated immediately.
+
+use bevy::prelude::*;
+
+use crate::{
+    blob::geno_blob_builder::{BlobGeno, GenericGenoNode},
+    brain::{
+        neat::NeatNN,
+        neuron::{AnyNN, GenericNN},
+        nn::BaseNN,
+    },
+    config::SpeciationConfig,
+    logger_info,
+};
+
+/// a species, represented by the best blob when it was last updated
+pub struct SpeciesInfo {
+    pub id: usize,
+    /// geno of the representative, nn_id index `nnvec` below
+    geno: BlobGeno,
+    nnvec: Vec<GenericNN>,
+    pub size: usize,
+    /// generations since the species appears
+    pub age: usize,
+    pub best_fitness: f32,
+    /// generations since `best_fitness` improved
+    pub stagnation: usize,
+}
+
+/// Bevy resource of all the species,
+/// and the compatibility threshold to decide whether two blobs are in the same species
+#[derive(Resource)]
+pub struct Species {
+    pub list: Vec<SpeciesInfo>,
+    pub threshold: f32,
+    next_id: usize,
+}
+
+impl Species {
+    pub fn new(config: &SpeciationConfig) -> Self {
+        Self {
+            list: Vec::new(),
+            threshold: config.threshold,
+            next_id: 0,
+        }
+    }
+
+    /// assign all blobs to species, update species stats and the threshold.
+    ///
+    /// `blobs` are the genos with nn_id index `nnvec`.
+    ///
+    /// return the index in `list` for each blob
+    pub fn speciate(
+        &mut self,
+        blobs: &[&BlobGeno],
+        scores: &[f32],
+        nnvec: &[GenericNN],
+        config: &SpeciationConfig,
+    ) -> Vec<usize> {
+        let mut assignment = Vec::<usize>::with_capacity(blobs.len());
+        for s in self.list.iter_mut() {
+            s.size = 0;
+        }
+
+        for &geno in blobs.iter() {
+            let found = self.list.iter().position(|s| {
+                blob_distance((geno, nnvec), (&s.geno, &s.nnvec), config) < self.threshold
+            });
+            let idx = match found {
+                Some(idx) => idx,
+                None => {
+                    let (geno, nnvec) = geno.detach_nn(nnvec);
+                    self.list.push(SpeciesInfo {
+                        id: self.next_id,
+                        geno,
+                        nnvec,
+                        size: 0,
+                        age: 0,
+                        best_fitness: f32::NEG_INFINITY,
+                        stagnation: 0,
+                    });
+                    self.next_id += 1;
+                    self.list.len() - 1
+                }
+            };
+            self.list[idx].size += 1;
+            assignment.push(idx);
+        }
+
+        // update stats and representatives
+        for (idx, s) in self.list.iter_mut().enumerate() {
+            let best = (0..blobs.len())
+                .filter(|&i| assignment[i] == idx)
+                .max_by(|&a, &b| {
+                    scores[a]
+                        .partial_cmp(&scores[b])
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+            let Some(best) = best else {
+                continue;
+            };
+            s.age += 1;
+            if scores[best] > s.best_fitness {
+                s.best_fitness = scores[best];
+                s.stagnation = 0;
+            } else {
+                s.stagnation += 1;
+            }
+            (s.geno, s.nnvec) = blobs[best].detach_nn(nnvec);
+        }
+
+        // drop empty species, keep assignment matched
+        let mut new_index = Vec::<Option<usize>>::new();
+        let mut count = 0;
+        for s in self.list.iter() {
+            if s.size > 0 {
+                new_index.push(Some(count));
+                count += 1;
+            } else {
+                new_index.push(None);
+            }
+        }
+        self.list.retain(|s| s.size > 0);
+        let assignment = assignment.iter().map(|&i| new_index[i].unwrap()).collect();
+
+        // move threshold towards the target species count
+        if self.list.len() < config.target_species {
+            self.threshold = (self.threshold - config.threshold_step).max(config.min_threshold);
+        } else if self.list.len() > config.target_species {
+            self.threshold += config.threshold_step;
+        }
+
+        assignment
+    }
+
+    /// log species stats
+    pub fn log(&self, iteration: u128, fitness_name: &str) {
+        logger_info!(
+            "iteration {}, {} species, threshold {:.5}",
+            iteration,
+            self.list.len(),
+            self.threshold
+        );
+        for s in self.list.iter() {
+            logger_info!(
+                "iteration {}, species {}, size {}, age {}, best_{} {:.5}, stagnation {}",
+                iteration,
+                s.id,
+                s.size,
+                s.age,
+                fitness_name,
+                s.best_fitness,
+                s.stagnation
+            );
+        }
+    }
+}
+
+/// divide the fitness by the size of blob's species.
+///
+/// Scores are shifted to be non-negative first, so that sharing always lowers the score
+/// of large species
+pub fn share_fitness(scores: &[f32], assignment: &[usize], species_len: usize) -> Vec<f32> {
+    let mut sizes = vec![0usize; species_len];
+    for &s in assignment.iter() {
+        sizes[s] += 1;
+    }
+    let min = scores.iter().cloned().fold(f32::INFINITY, f32::min);
+    scores
+        .iter()
+        .zip(assignment.iter())
+        .map(|(score, &s)| (score - min) / sizes[s] as f32)
+        .collect()
+}
+
+/// distance between two blobs: weighted TED plus mean weight distance of the NNs
+/// of blocks at the same position.
+///
+/// The center block is the root node, its NN is the brain, so brains are compared as well
+pub fn blob_distance(
+    a: (&BlobGeno, &[GenericNN]),
+    b: (&BlobGeno, &[GenericNN]),
+    config: &SpeciationConfig,
+) -> f32 {
+    let ted = a.0.vec_tree.tree_edit_distance(&b.0.vec_tree) as f32;
+
+    let mut nn_distance = 0.0;
+    let mut count = 0;
+    for (node_a, node_b) in a.0.vec_tree.nodes.iter().zip(b.0.vec_tree.nodes.iter()) {
+        if let (Some(GenericGenoNode::Child(node_a)), Some(GenericGenoNode::Child(node_b))) =
+            (node_a, node_b)
+        {
+            if let (Some(id_a), Some(id_b)) = (node_a.nn_id, node_b.nn_id) {
+                if let Some(d) = nn_distance_of(&a.1[id_a], &b.1[id_b]) {
+                    nn_distance += d;
+                    count += 1;
+                }
+            }
+        }
+    }
+    if count > 0 {
+        nn_distance /= count as f32;
+    }
+
+    config.ted_weight * ted + config.nn_weight * nn_distance
+}
+
+/// mean absolute difference of weights and biases, `None` if the NNs don't match
+fn nn_distance_of(a: &GenericNN, b: &GenericNN) -> Option<f32> {
+    match (a, b) {
+        (GenericNN::BLOCKNN(a), GenericNN::BLOCKNN(b)) => {
+            let inward = any_nn_distance(&a.inward_nn.nn, &b.inward_nn.nn)?;
+            let outward = any_nn_distance(&a.outward_nn.nn, &b.outward_nn.nn)?;
+            Some((inward + outward) / 2.0)
+        }
+        (GenericNN::BRAINNN(a), GenericNN::BRAINNN(b)) => any_nn_distance(&a.nn, &b.nn),
+        _ => None,
+    }
+}
+
+fn any_nn_distance(a: &AnyNN, b: &AnyNN) -> Option<f32> {
+    match (a, b) {
+        (AnyNN::Base(a), AnyNN::Base(b)) => base_nn_distance(a, b),
+        (AnyNN::Neat(a), AnyNN::Neat(b)) => Some(neat_nn_distance(a, b)),
+        _ => None,
+    }
+}
+
+/// NEAT compatibility distance: ratio of unmatched connections
+/// plus mean absolute weight difference of matching ones.
+///
+/// Genes only match if they also connect the same nodes, like in `crossover_neat_nn`
+fn neat_nn_distance(a: &NeatNN, b: &NeatNN) -> f32 {
+    let mut matching = 0;
+    let mut weight_diff = 0.0;
+    for conn in a.connections.iter() {
+        if let Some(other) = b.connections.iter().find(|c| {
+            c.innovation == conn.innovation && c.from == conn.from && c.to == conn.to
+        }) {
+            matching += 1;
+            weight_diff += (conn.weight - other.weight).abs();
+        }
+    }
+    let unmatched = a.connections.len() + b.connections.len() - 2 * matching;
+    let size = a.connections.len().max(b.connections.len()).max(1);
+    unmatched as f32 / size as f32 + weight_diff / matching.max(1) as f32
+}
+
+fn base_nn_distance(a: &BaseNN, b: &BaseNN) -> Option<f32> {
+    if a.layers.len() != b.layers.len() {
+        return None;
+    }
+    let mut sum = 0.0;
+    let mut count = 0;
+    for (la, lb) in a.layers.iter().zip(b.layers.iter()) {
+        if la.weights.shape() != lb.weights.shape() || la.bias.shape() != lb.bias.shape() {
+            return None;
+        }
+        sum += (&la.weights - &lb.weights).mapv(f32::abs).sum();
+        sum += (&la.bias - &lb.bias).mapv(f32::abs).sum();
+        count += la.weights.len() + la.bias.len();
+    }
+    Some(sum / count.max(1) as f32)
+}
+#[cfg(test)]
+mod species_test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{
+        blob::geno_blob_builder::GenoNode,
+        brain::{neat::Innovations, neuron::BrainNN},
+    };
+
+    /// a center block, with a block on its top if `limb`
+    fn geno(limb: bool) -> BlobGeno {
+        let mut geno = BlobGeno::default();
+        geno.vec_tree.nodes[0] = Some(GenericGenoNode::Child(GenoNode::from_nn_id(0)));
+        if limb {
+            geno.vec_tree.nodes[1] = Some(GenericGenoNode::Child(GenoNode::from_nn_id(1)));
+        }
+        geno
+    }
+
+    fn brains(n: usize, rng: &mut StdRng) -> Vec<GenericNN> {
+        (0..n)
+            .map(|_| GenericNN::BRAINNN(BrainNN::new_rand(&mut Innovations::default(), rng)))
+            .collect()
+    }
+
+    fn test_config() -> SpeciationConfig {
+        SpeciationConfig {
+            enabled: true,
+            target_species: 1,
+            threshold: 0.5,
+            threshold_step: 0.1,
+            min_threshold: 0.3,
+            ted_weight: 1.0,
+            nn_weight: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_speciate() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let nnvec = brains(2, &mut rng);
+        let config = test_config();
+        let (small, large) = (geno(false), geno(true));
+
+        let mut species = Species::new(&config);
+        let assignment =
+            species.speciate(&[&small, &large, &small], &[1.0, 2.0, 3.0], &nnvec, &config);
+        assert_eq!(assignment, vec![0, 1, 0]);
+        let sizes: Vec<usize> = species.list.iter().map(|s| s.size).collect();
+        assert_eq!(sizes, vec![2, 1]);
+        assert_eq!(species.list[0].best_fitness, 3.0);
+        // more species than the target, the threshold grows
+        assert!((species.threshold - 0.6).abs() < 1e-6);
+
+        // the species of small blobs is empty and dropped
+        let assignment = species.speciate(&[&large, &large], &[1.0, 0.5], &nnvec, &config);
+        assert_eq!(assignment, vec![0, 0]);
+        assert_eq!(species.list.len(), 1);
+        assert_eq!(species.list[0].id, 1);
+        assert_eq!(species.list[0].stagnation, 1);
+        assert!((species.threshold - 0.6).abs() < 1e-6);
+
+        // fewer species than the target, the threshold shrinks until the minimum
+        let config = SpeciationConfig {
+            target_species: 3,
+            ..config
+        };
+        for _ in 0..5 {
+            species.speciate(&[&large], &[1.0], &nnvec, &config);
+        }
+        assert_eq!(species.threshold, config.min_threshold);
+    }
+
+    #[test]
+    fn test_share_fitness() {
+        // shifted by the minimum, then divided by the species size
+        let shared = share_fitness(&[-1.0, 1.0, 3.0], &[0, 0, 1], 2);
+        assert_eq!(shared, vec![0.0, 1.0, 4.0]);
+    }
+
+    #[test]
+    fn test_brain_distance() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let (a, b) = (brains(1, &mut rng), brains(1, &mut rng));
+        let config = SpeciationConfig {
+            ted_weight: 0.0,
+            nn_weight: 1.0,
+            ..test_config()
+        };
+        let center = geno(false);
+        assert_eq!(blob_distance((&center, &a), (&center, &a), &config), 0.0);
+        assert!(blob_distance((&center, &a), (&center, &b), &config) > 0.0);
+    }
+}
@@ -0,0 +1,180 @@
+//! touch sensing, all contacts of a block aggregated per frame
+
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashMap};
+use bevy_rapier2d::prelude::ContactForceEvent;
+
+use crate::{
+    blob::block::BlockAnchors,
+    componet::{BlobEntityIndex, ColliderFlag},
+    consts::{TOUCH_FORCE_SCALE, TOUCH_MAX_CONTACTS},
+};
+
+/// contacts of a block in one frame
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Touch {
+    /// touching a wall
+    pub wall: bool,
+    /// touching a block of another blob
+    pub other_blob: bool,
+    /// sum of contact forces on the block
+    pub force: Vec2,
+    /// largest force magnitude among the contacts
+    pub max_magnitude: f32,
+    pub count: usize,
+    /// contacts on each face, same order as `ParentAnchor`
+    pub faces: [bool; 4],
+}
+
+impl Touch {
+    /// `force` is the force on this block, `face` the face index of the contact
+    pub fn add(&mut self, other: &ColliderFlag, own_blob: u32, force: Vec2, face: Option<usize>) {
+        match other {
+            ColliderFlag::WALL => self.wall = true,
+            ColliderFlag::BLOCK(BlobEntityIndex(Some(oid))) if *oid != own_blob => {
+                self.other_blob = true
+            }
+            _ => (),
+        }
+        self.force += force;
+        self.max_magnitude = self.max_magnitude.max(force.length());
+        self.count += 1;
+        if let Some(face) = face {
+            self.faces[face] = true;
+        }
+    }
+
+    /// contact count in [0, 1], full at `TOUCH_MAX_CONTACTS`
+    pub fn count_input(&self) -> f32 {
+        self.count.min(TOUCH_MAX_CONTACTS) as f32 / TOUCH_MAX_CONTACTS as f32
+    }
+
+    /// largest force magnitude in [0, 1), 0.5 at `TOUCH_FORCE_SCALE`
+    pub fn magnitude_input(&self) -> f32 {
+        squash_force(self.max_magnitude)
+    }
+
+    /// summed force, with its length squashed like `magnitude_input`
+    pub fn force_input(&self) -> [f32; 2] {
+        let length = self.force.length();
+        if length == 0.0 {
+            return [0.0, 0.0];
+        }
+        (self.force * (squash_force(length) / length)).to_array()
+    }
+}
+
+fn squash_force(magnitude: f32) -> f32 {
+    magnitude / (magnitude + TOUCH_FORCE_SCALE)
+}
+
+/// face of a block hit by the direction `local_dir` (in block frame) from its center.
+///
+/// `None` for a zero direction
+pub fn contact_face(anchors: &BlockAnchors, local_dir: Vec2) -> Option<usize> {
+    if local_dir == Vec2::ZERO {
+        return None;
+    }
+    // the face reached first by the ray, anchors are the face centers
+    (0..4)
+        .map(|i| {
+            let anchor = anchors.of(i);
+            (i, local_dir.dot(anchor) / anchor.length_squared())
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
+}
+
+/// queries needed to aggregate contacts
+#[derive(SystemParam)]
+pub struct TouchSensor<'w, 's> {
+    flag_q: Query<'w, 's, &'static ColliderFlag>,
+    body_q: Query<'w, 's, (&'static Transform, &'static BlockAnchors)>,
+}
+
+impl TouchSensor<'_, '_> {
+    /// bucket contact events of this frame by block in one pass.
+    ///
+    /// Colliders that are not blocks of a blob are skipped
+    pub fn collect<'a>(
+        &self,
+        events: impl IntoIterator<Item = &'a ContactForceEvent>,
+    ) -> HashMap<Entity, Touch> {
+        let mut touches = HashMap::<Entity, Touch>::new();
+        for event in events {
+            // `total_force` points from collider1 to collider2,
+            // which is the force on collider2
+            for (own, other, force) in [
+                (event.collider1, event.collider2, -event.total_force),
+                (event.collider2, event.collider1, event.total_force),
+            ] {
+                let (Ok(ColliderFlag::BLOCK(BlobEntityIndex(Some(sid)))), Ok(oflag)) =
+                    (self.flag_q.get(own), self.flag_q.get(other))
+                else {
+                    continue;
+                };
+                // the contact is on the side the force pushes away from
+                let face = self.body_q.get(own).ok().and_then(|(transform, anchors)| {
+                    let local = transform.rotation.inverse() * (-force).extend(0.0);
+                    contact_face(anchors, local.truncate())
+                });
+                touches
+                    .entry(own)
+                    .or_default()
+                    .add(oflag, *sid, force, face);
+            }
+        }
+        touches
+    }
+}
+
+#[cfg(test)]
+mod touch_test {
+    use super::*;
+
+    #[test]
+    fn test_contact_face() {
+        // wide block, 40 wide and 10 high
+        let anchors = BlockAnchors::from_xy(20.0, 5.0);
+        assert_eq!(contact_face(&anchors, Vec2::new(0.0, 1.0)), Some(0));
+        assert_eq!(contact_face(&anchors, Vec2::new(1.0, -1.0)), Some(1));
+        assert_eq!(contact_face(&anchors, Vec2::new(-1.0, 0.2)), Some(2));
+        assert_eq!(contact_face(&anchors, Vec2::ZERO), None);
+
+        let mut touch = Touch::default();
+        touch.add(&ColliderFlag::WALL, 1, Vec2::new(3.0, 4.0), Some(1));
+        touch.add(
+            &ColliderFlag::BLOCK(BlobEntityIndex(Some(1))),
+            1,
+            Vec2::new(1.0, 0.0),
+            Some(3),
+        );
+        assert!(touch.wall && !touch.other_blob);
+        assert_eq!(touch.force, Vec2::new(4.0, 4.0));
+        assert_eq!(touch.max_magnitude, 5.0);
+        assert_eq!(touch.count, 2);
+        assert_eq!(touch.faces, [false, true, false, true]);
+    }
+
+    #[test]
+    fn test_touch_inputs() {
+        let mut touch = Touch::default();
+        assert_eq!(touch.count_input(), 0.0);
+        assert_eq!(touch.magnitude_input(), 0.0);
+        assert_eq!(touch.force_input(), [0.0, 0.0]);
+
+        let force = Vec2::new(0.0, TOUCH_FORCE_SCALE);
+        touch.add(&ColliderFlag::WALL, 1, force, None);
+        assert_eq!(touch.count_input(), 1.0 / TOUCH_MAX_CONTACTS as f32);
+        assert_eq!(touch.magnitude_input(), 0.5);
+        assert_eq!(touch.force_input(), [0.0, 0.5]);
+
+        // many and huge contacts stay in range
+        for _ in 0..10 {
+            touch.add(&ColliderFlag::WALL, 1, force * 1e3, None);
+        }
+        assert_eq!(touch.count_input(), 1.0);
+        assert!(touch.magnitude_input() < 1.0);
+        let [x, y] = touch.force_input();
+        assert!(x == 0.0 && y > 0.99 && y < 1.0);
+    }
+}
@@ -2,152 +2,333 @@
 
 // TODO: Currently the crowing distance only considered the morphyology distance, need to consider the distance of neural network.
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use bevy::prelude::*;
 use rand::prelude::*;
 use rand_distr::WeightedIndex;
 
 use crate::{
-    blob::{blob::BlobInfo, block::NeuronId, geno_blob_builder::BlobGeno},
+    blob::{blob::BlobInfo, block::NeuronId, geno_blob_builder::BlobGeno, lineage::Lineage},
     brain::{neuron::GenericNN, resource::BevyBlockNeurons},
-    consts::{
-        HYBRID_RATE, ITERATION_LENGTH, NEW_ITERATION_KEYCODE, POPULATION, TRAIN_MOVE_SURVIVAL_RATE,
-    },
-    contorl::contorl::get_center,
+    config::SimConfig,
+    contorl::contorl::{get_center, get_island_center, island_sizes},
     logger_info,
+    mutate::crossover::{crossover_geno, crossover_nn},
+    rng::SimRng,
 };
 
-use super::resource::{Frames, TrainMutPipe, TED};
+use super::{
+    fitness::TrainFitness,
+    hall_of_fame::HallOfFame,
+    lineage::LineageTracker,
+    map_elites::MapElites,
+    novelty::{behaviour_descriptor, blend, NoveltyArchive},
+    resource::{Frames, TrainMutPipe, TED},
+    selection::{nsga2_select, Objective, Selection},
+    species::{share_fitness, Species},
+};
 
-/// main training function for blob's swim moving.
+/// main training function for blob's moving.
 /// 
 /// When current iteration ends, the function will be called.
 /// 
-/// Preform tournament selection base on moving distance and crowding distance
+/// Preform tournament selection base on `TrainFitness` and crowding distance,
+/// or NSGA-II selection over `objectives`, or draw parents from the MAP-Elites archive,
+/// decided by `TrainingConfig::selection`.
+/// 
+/// If novelty search is enabled, fitness is blended with novelty before selection.
+///
+/// If speciation is enabled, fitness is shared inside each species before selection
+///
+/// With more than one island, selection and reproduction happen inside each island,
+/// top blobs migrate to the next island every `migration_interval` generations.
+/// MAP-Elites ignores islands.
 /// 
-/// `POPULATION == 1` in will make thread panic since it never trains
-pub fn train_move_swim(
+/// `population == 1` in will make thread panic since it never trains
+pub fn train_move(
     entity_geno_info_q: Query<(Entity, (&BlobGeno, &BlobInfo))>,
     nn_q: Query<(&Parent, &NeuronId)>,
     mut bbn: ResMut<BevyBlockNeurons>,
     mut pipe: ResMut<TrainMutPipe>,
     input: Res<Input<KeyCode>>,
     frames: Res<Frames>,
+    config: Res<SimConfig>,
+    fitness: Res<TrainFitness>,
+    mut species: ResMut<Species>,
+    mut hall_of_fame: ResMut<HallOfFame>,
+    mut lineage: ResMut<LineageTracker>,
+    mut archive: ResMut<NoveltyArchive>,
+    mut map_elites: ResMut<MapElites>,
+    mut rng: ResMut<SimRng>,
 ) {
-    if input.just_pressed(NEW_ITERATION_KEYCODE) || iteration_end(frames) {
+    let iteration = frames.0 / config.training.iteration_length as u128;
+    if input.just_pressed(config.keys.new_iteration) || iteration_end(frames, &config) {
         let nnvec = &mut bbn.nnvec;
-        let mut blob_vec_move: Vec<(Entity, (BlobGeno, BlobInfo))> = Vec::new();
-        let mut blob_vec_ted: Vec<(Entity, (BlobGeno, BlobInfo))> = Vec::new();
+        let mut blob_vec: Vec<(Entity, (BlobGeno, BlobInfo))> = Vec::new();
+        let mut scores: Vec<f32> = Vec::new();
         for (e, (geno, info)) in entity_geno_info_q.iter() {
-            blob_vec_move.push((e, (geno.clone(), info.clone())));
-            blob_vec_ted.push((e, (geno.clone(), info.clone())));
+            scores.push(fitness.score(geno, info));
+            blob_vec.push((e, (geno.clone(), info.clone())));
         }
-
-        // move mag
-        blob_vec_move.sort_by(|a, b| {
-            let mag_a =
-                a.1 .1
-                    .move_distance
-                    .iter()
-                    .fold(0.0, |acc, &x| acc + x * x)
-                    .sqrt();
-            let mag_b =
-                b.1 .1
-                    .move_distance
-                    .iter()
-                    .fold(0.0, |acc, &x| acc + x * x)
-                    .sqrt();
-            mag_b
-                .partial_cmp(&mag_a)
+        lineage.record(
+            iteration,
+            blob_vec.iter_mut().map(|(_, (geno, _))| geno).collect(),
+            &scores,
+        );
+
+        // best blobs by fitness (not shared)
+        let mut order: Vec<usize> = (0..scores.len()).collect();
+        order.sort_by(|&a, &b| {
+            scores[b]
+                .partial_cmp(&scores[a])
                 .unwrap_or(std::cmp::Ordering::Equal)
         });
 
-        blob_vec_ted.sort_by(|a, b| {
-            let mag_a = a.1 .1.crowding_distance;
-            let mag_b = b.1 .1.crowding_distance;
-            mag_b
-                .partial_cmp(&mag_a)
-                .unwrap_or(std::cmp::Ordering::Equal)
-        });
+        let hof_size = config.training.hall_of_fame_size;
+        if hof_size > 0 {
+            let genovec: Vec<&BlobGeno> =
+                order.iter().take(hof_size).map(|&i| &blob_vec[i].1 .0).collect();
+            let top_scores: Vec<f32> = order.iter().take(hof_size).map(|&i| scores[i]).collect();
+            if hall_of_fame.update(&genovec, &top_scores, nnvec, iteration, hof_size) {
+                hall_of_fame.save(&config.io.export_path);
+            }
+        }
 
-        let split_idx = (blob_vec_move.len() as f32 * TRAIN_MOVE_SURVIVAL_RATE).ceil() as usize;
+        // cells keep the best-moving blob, parents come from the archive instead of survivers
+        if config.training.selection == Selection::MapElites {
+            let split_idx =
+                (blob_vec.len() as f32 * config.training.survival_rate).ceil() as usize;
+            let genovec: Vec<&BlobGeno> = blob_vec.iter().map(|(_, (geno, _))| geno).collect();
+            if map_elites.insert(&genovec, &scores, nnvec, iteration, &config.map_elites) {
+                map_elites.save(&config.io.export_path);
+            }
+            map_elites.log(iteration, fitness.name(), &config.map_elites);
 
-        // tournament selection
-        let (survivers_move, _outcasts) = blob_vec_move.split_at_mut(split_idx);
-        hybrid_selection(survivers_move, &blob_vec_ted);
+            let (mut new_genovec, mut new_nnvec) = map_elites.sample(split_idx, &mut rng.train);
+            // archived blobs are copied, the copies are new blobs
+            for geno in new_genovec.iter_mut() {
+                geno.lineage = Lineage::child_of(geno.lineage.id.into_iter().collect());
+            }
+            let mut infovec = vec![BlobInfo::default(); new_genovec.len()];
+            reproduce(
+                &mut new_genovec,
+                &mut infovec,
+                &mut new_nnvec,
+                config.training.population,
+                &config,
+                &mut rng.train,
+            );
+            set_centers(&mut infovec, &get_center(&config, &mut rng.spawn));
+            pipe.push(new_genovec, infovec, new_nnvec, 0);
+            return;
+        }
 
-        let (mut new_genovec, mut infovec, mut new_nnvec) =
-            clean_outcast(survivers_move, nn_q, nnvec);
+        // elites are picked by fitness (not shared or blended)
+        let raw_scores = scores.clone();
 
-        // reproduce
-        reproduce(&mut new_genovec, &mut infovec, &mut new_nnvec);
+        // novelty search
+        if config.novelty.enabled {
+            let descriptors: Vec<Vec<f32>> = blob_vec
+                .iter()
+                .map(|(_, (_, info))| behaviour_descriptor(info, &config.novelty))
+                .collect();
+            let novelty = archive.evaluate(&descriptors, &config.novelty);
+            archive.log(iteration, &novelty);
+            scores = blend(&scores, &novelty, config.novelty.weight);
+        }
 
-        // println!("{:#?}",new_genovec);
-        // println!("nnveclen: {:#?}",new_nnvec.len());
+        // fitness sharing inside species
+        if config.speciation.enabled {
+            let genovec: Vec<&BlobGeno> = blob_vec.iter().map(|(_, (geno, _))| geno).collect();
+            let assignment = species.speciate(&genovec, &scores, nnvec, &config.speciation);
+            species.log(iteration, fitness.name());
+            scores = share_fitness(&scores, &assignment, species.list.len());
+        }
 
-        pipe.push(new_genovec, infovec, new_nnvec);
-    }
-}
+        // selection inside each island
+        let islands = assign_islands(&blob_vec, &config);
+        let mut island_survivers = Vec::<Vec<(Entity, (BlobGeno, BlobInfo))>>::new();
+        let mut island_elites = Vec::<usize>::new();
+        for indices in islands.iter() {
+            let (survivers, elite_len) = select_survivers(
+                indices.iter().map(|&i| blob_vec[i].clone()).collect(),
+                indices.iter().map(|&i| raw_scores[i]).collect(),
+                indices.iter().map(|&i| scores[i]).collect(),
+                &fitness,
+                iteration,
+                &config,
+                &mut rng.train,
+            );
+            island_survivers.push(survivers);
+            island_elites.push(elite_len);
+        }
 
-/// main training function for blob's walk moving.
-/// 
-/// When current iteration ends, the function will be called.
-/// 
-/// Preform tournament selection base on moving distance (only on x axis) and crowding distance
-/// 
-/// `POPULATION == 1` in will make thread panic since it never trains
-pub fn train_move_walk(
-    entity_geno_info_q: Query<(Entity, (&BlobGeno, &BlobInfo))>,
-    nn_q: Query<(&Parent, &NeuronId)>,
-    mut bbn: ResMut<BevyBlockNeurons>,
-    mut pipe: ResMut<TrainMutPipe>,
-    input: Res<Input<KeyCode>>,
-    frames: Res<Frames>,
-) {
-    if input.just_pressed(NEW_ITERATION_KEYCODE) || iteration_end(frames) {
-        let nnvec = &mut bbn.nnvec;
-        let mut blob_vec_move: Vec<(Entity, (BlobGeno, BlobInfo))> = Vec::new();
-        let mut blob_vec_ted: Vec<(Entity, (BlobGeno, BlobInfo))> = Vec::new();
-        for (e, (geno, info)) in entity_geno_info_q.iter() {
-            blob_vec_move.push((e, (geno.clone(), info.clone())));
-            blob_vec_ted.push((e, (geno.clone(), info.clone())));
+        let islands_config = &config.islands;
+        if islands.len() > 1
+            && islands_config.migration_interval > 0
+            && iteration > 0
+            && iteration % islands_config.migration_interval as u128 == 0
+        {
+            let score_map: HashMap<Entity, f32> =
+                blob_vec.iter().map(|(e, _)| *e).zip(scores.iter().copied()).collect();
+            let score_of =
+                |entity: Entity| score_map.get(&entity).copied().unwrap_or(f32::NEG_INFINITY);
+            let count = migrate(
+                &mut island_survivers,
+                &island_elites,
+                score_of,
+                islands_config.migration_rate,
+            );
+            logger_info!(
+                "iteration {}, {} blobs migrated between {} islands",
+                iteration,
+                count,
+                islands.len()
+            );
         }
 
-        // x axis move
-        blob_vec_move.sort_by(|a, b| {
-            let mag_a = a.1 .1.move_distance[0];
-            let mag_b = b.1 .1.move_distance[0];
-            mag_b
-                .partial_cmp(&mag_a)
-                .unwrap_or(std::cmp::Ordering::Equal)
-        });
+        let island_lens: Vec<usize> = island_survivers.iter().map(|s| s.len()).collect();
+        let mut survivers: Vec<(Entity, (BlobGeno, BlobInfo))> =
+            island_survivers.into_iter().flatten().collect();
+
+        let (mut genovec, mut infovec, mut new_nnvec) =
+            clean_outcast(&mut survivers, nn_q, nnvec);
+        // migrants share NNs with their origin
+        unshare_nn(&mut genovec, &mut new_nnvec);
+
+        // reproduce inside each island, elites of all islands go in front
+        let island_sizes = island_sizes(&config);
+        let mut elite_genovec = Vec::<BlobGeno>::new();
+        let mut elite_infovec = Vec::<BlobInfo>::new();
+        let mut rest_genovec = Vec::<BlobGeno>::new();
+        let mut rest_infovec = Vec::<BlobInfo>::new();
+        for (island, &len) in island_lens.iter().enumerate() {
+            let mut island_genovec: Vec<BlobGeno> = genovec.drain(..len).collect();
+            let mut island_infovec: Vec<BlobInfo> = infovec.drain(..len).collect();
+            reproduce(
+                &mut island_genovec,
+                &mut island_infovec,
+                &mut new_nnvec,
+                island_sizes[island],
+                &config,
+                &mut rng.train,
+            );
+            set_centers(
+                &mut island_infovec,
+                &get_island_center(&config, island, &mut rng.spawn),
+            );
+
+            let elite_len = island_elites[island];
+            rest_genovec.extend(island_genovec.drain(elite_len..));
+            rest_infovec.extend(island_infovec.drain(elite_len..));
+            elite_genovec.append(&mut island_genovec);
+            elite_infovec.append(&mut island_infovec);
+        }
+        let elite_len = elite_genovec.len();
+        elite_genovec.append(&mut rest_genovec);
+        elite_infovec.append(&mut rest_infovec);
 
-        blob_vec_ted.sort_by(|a, b| {
-            let mag_a = a.1 .1.crowding_distance;
-            let mag_b = b.1 .1.crowding_distance;
-            mag_b
-                .partial_cmp(&mag_a)
-                .unwrap_or(std::cmp::Ordering::Equal)
-        });
+        // println!("{:#?}",new_genovec);
+        // println!("nnveclen: {:#?}",new_nnvec.len());
 
-        let split_idx = (blob_vec_move.len() as f32 * TRAIN_MOVE_SURVIVAL_RATE).ceil() as usize;
+        pipe.push(elite_genovec, elite_infovec, new_nnvec, elite_len);
+    }
+}
 
-        // tournament selection
-        let (survivers_move, _outcasts) = blob_vec_move.split_at_mut(split_idx);
-        hybrid_selection(survivers_move, &blob_vec_ted);
+/// select survivers of a pool (the whole population or an island).
+///
+/// Elites are picked by `raw_scores`, the rest by `scores`.
+///
+/// return the survivers with elites in front, and the number of elites
+fn select_survivers(
+    blob_vec: Vec<(Entity, (BlobGeno, BlobInfo))>,
+    raw_scores: Vec<f32>,
+    scores: Vec<f32>,
+    fitness: &TrainFitness,
+    iteration: u128,
+    config: &SimConfig,
+    rng: &mut impl Rng,
+) -> (Vec<(Entity, (BlobGeno, BlobInfo))>, usize) {
+    let split_idx = (blob_vec.len() as f32 * config.training.survival_rate).ceil() as usize;
+
+    // elites skip selection and mutation
+    let mut order: Vec<usize> = (0..raw_scores.len()).collect();
+    order.sort_by(|&a, &b| {
+        raw_scores[b]
+            .partial_cmp(&raw_scores[a])
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let elites: Vec<(Entity, (BlobGeno, BlobInfo))> = order
+        .iter()
+        .take(config.training.elite_count.min(split_idx))
+        .map(|&i| blob_vec[i].clone())
+        .collect();
 
-        let (mut new_genovec, mut infovec, mut new_nnvec) =
-            clean_outcast(survivers_move, nn_q, nnvec);
+    let mut blob_vec_ted = blob_vec.clone();
+    let mut scored_vec: Vec<(f32, (Entity, (BlobGeno, BlobInfo)))> =
+        scores.into_iter().zip(blob_vec.into_iter()).collect();
 
-        // reproduce
-        reproduce(&mut new_genovec, &mut infovec, &mut new_nnvec);
+    // fitness
+    scored_vec.sort_by(|a, b| {
+        b.0.partial_cmp(&a.0)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let (scores, blob_vec_move): (Vec<f32>, Vec<(Entity, (BlobGeno, BlobInfo))>) =
+        scored_vec.into_iter().unzip();
 
-        // println!("{:#?}",new_genovec);
-        // println!("nnveclen: {:#?}",new_nnvec.len());
+    blob_vec_ted.sort_by(|a, b| {
+        let mag_a = a.1 .1.crowding_distance;
+        let mag_b = b.1 .1.crowding_distance;
+        mag_b
+            .partial_cmp(&mag_a)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
 
-        pipe.push(new_genovec, infovec, new_nnvec);
-    }
+    let survivers: Vec<(Entity, (BlobGeno, BlobInfo))> = match config.training.selection {
+        Selection::Hybrid => {
+            // tournament selection
+            let mut survivers_move = blob_vec_move[..split_idx].to_vec();
+            hybrid_selection(
+                &mut survivers_move,
+                &blob_vec_ted,
+                config.training.hybrid_rate,
+                rng,
+            );
+            survivers_move
+        }
+        Selection::Nsga2 => {
+            let objectives: Vec<Vec<f32>> = blob_vec_move
+                .iter()
+                .zip(scores.iter())
+                .map(|((_, (geno, info)), &score)| {
+                    config.training.objectives
+                        .iter()
+                        .map(|objective| objective.value(score, fitness, geno, info))
+                        .collect()
+                })
+                .collect();
+            let (selected, front) = nsga2_select(&objectives, split_idx);
+            log_pareto_front(iteration, &config.training.objectives, &objectives, &front);
+            selected.iter().map(|&i| blob_vec_move[i].clone()).collect()
+        }
+        // handled in `train_move`
+        Selection::MapElites => unreachable!(),
+    };
+
+    // put elites in front
+    let elite_len = elites.len();
+    let elite_entities: Vec<Entity> = elites.iter().map(|(e, _)| *e).collect();
+    let survivers = elites
+        .into_iter()
+        .chain(
+            survivers
+                .into_iter()
+                .filter(|(e, _)| !elite_entities.contains(e)),
+        )
+        .take(split_idx)
+        .collect();
+    (survivers, elite_len)
 }
 
 /// determine the final surviers by random select blobs from
@@ -157,9 +338,10 @@ pub fn train_move_walk(
 fn hybrid_selection(
     survivers_move: &mut [(Entity, (BlobGeno, BlobInfo))],
     blob_vec_ted: &Vec<(Entity, (BlobGeno, BlobInfo))>,
+    hybrid_rate: f32,
+    rng: &mut impl Rng,
 ) {
-    let mut rng: ThreadRng = thread_rng();
-    let x = (HYBRID_RATE * survivers_move.len() as f32) as usize;
+    let x = (hybrid_rate * survivers_move.len() as f32) as usize;
     let bias_factor = 4.0;
 
     // Generate the weighted distribution
@@ -173,13 +355,13 @@ fn hybrid_selection(
     for _ in 0..x {
         let rand_surviver_idx = rng.gen_range(0..survivers_move.len());
 
-        let mut blobvec_idx = WeightedIndex::new(&weights).unwrap().sample(&mut rng);
+        let mut blobvec_idx = WeightedIndex::new(&weights).unwrap().sample(rng);
 
         // Ensure the selected index is unique and its Entity is not already in survivers_move
         while chosen_indices.contains(&blobvec_idx)
             || survivers_entities.contains(&blob_vec_ted[blobvec_idx].0)
         {
-            blobvec_idx = WeightedIndex::new(&weights).unwrap().sample(&mut rng);
+            blobvec_idx = WeightedIndex::new(&weights).unwrap().sample(rng);
         }
 
         chosen_indices.insert(blobvec_idx);
@@ -253,147 +435,323 @@ fn clean_outcast(
     (new_geno_vec, infovec, nnvec.clone())
 }
 
-/// reproduce the blob to the target population
+/// reproduce the blob to `population`
 ///
-/// this function will reset spawn position of all blobs,
-/// the position won't inherit
+/// new blobs are cloned from survivers, or crossovered from two of them
+/// with chance `crossover_rate`
+///
+/// spawn positions are not set here, see `set_centers`
 ///
 /// new NN will be append to nnvec
-fn reproduce(genovec: &mut Vec<BlobGeno>, infovec: &mut Vec<BlobInfo>, nnvec: &mut Vec<GenericNN>) {
+fn reproduce(
+    genovec: &mut Vec<BlobGeno>,
+    infovec: &mut Vec<BlobInfo>,
+    nnvec: &mut Vec<GenericNN>,
+    population: usize,
+    config: &SimConfig,
+    rng: &mut impl Rng,
+) {
     assert_eq!(genovec.len(), infovec.len());
-    assert!(genovec.len() < POPULATION);
-
-    let mut rng: ThreadRng = thread_rng();
+    assert!(!genovec.is_empty() && genovec.len() <= population);
 
     let mut new_genovec: Vec<BlobGeno> = Vec::new();
     let mut new_infovec: Vec<BlobInfo> = Vec::new();
     let mut new_nnvec: Vec<GenericNN> = Vec::new();
 
-    loop {
+    while new_genovec.len() + genovec.len() < population {
         let chosen_idx: usize = rng.gen_range(0..genovec.len());
         let mut new_geno = genovec.get(chosen_idx).unwrap().clone();
         let new_info = infovec.get(chosen_idx).unwrap().clone();
+        let mut parents: Vec<u64> = new_geno.lineage.id.into_iter().collect();
+
+        // crossover with another surviver
+        let mut nn_pairs = Vec::<(usize, usize)>::new();
+        if genovec.len() > 1 && rng.gen_bool(config.training.crossover_rate as f64) {
+            let mut mate_idx: usize = rng.gen_range(0..genovec.len() - 1);
+            if mate_idx >= chosen_idx {
+                mate_idx += 1;
+            }
+            if let Some((child, pairs)) =
+                crossover_geno(&new_geno, &genovec[mate_idx], rng)
+            {
+                new_geno = child;
+                nn_pairs = pairs;
+                parents.extend(genovec[mate_idx].lineage.id);
+            }
+        }
+        new_geno.lineage = Lineage::child_of(parents);
+
         for nn_id in new_geno.all_nn_ids_mut() {
             let copied_id = nn_id.unwrap();
-            let new_nn = nnvec.get(copied_id).unwrap().clone();
+            let mut new_nn = nnvec.get(copied_id).unwrap().clone();
+            if let Some(&(_, mate_id)) = nn_pairs.iter().find(|(id, _)| *id == copied_id) {
+                if rng.gen_bool(config.training.nn_crossover_prob as f64) {
+                    crossover_nn(&mut new_nn, &nnvec[mate_id], rng);
+                }
+            }
             new_nnvec.push(new_nn);
             // modify nn_id
             *nn_id = Some(new_nnvec.len() + nnvec.len() - 1)
         }
         new_genovec.push(new_geno);
         new_infovec.push(new_info);
-
-        if new_genovec.len() + genovec.len() == POPULATION {
-            break;
-        }
     }
 
     genovec.append(&mut new_genovec);
     infovec.append(&mut new_infovec);
     nnvec.append(&mut new_nnvec);
+}
 
-    let rand_centers = get_center();
-    assert_eq!(infovec.len(), rand_centers.len());
-    for (center, info) in rand_centers.iter().zip(infovec.iter_mut()) {
+/// reset spawn position of all blobs, the position won't inherit
+fn set_centers(infovec: &mut [BlobInfo], centers: &[(f32, f32)]) {
+    assert_eq!(infovec.len(), centers.len());
+    for (center, info) in centers.iter().zip(infovec.iter_mut()) {
         info.center_block_pos = Vec2::from_array([center.0, center.1])
     }
 }
 
-/// determin if iteration ends
-fn iteration_end(frames: Res<Frames>) -> bool {
-    let cur_gen_frame_cnt = frames.0 % ITERATION_LENGTH as u128;
-    if cur_gen_frame_cnt == 0 && frames.0 != 0 {
-        true
-    } else {
-        false
-    }
-}
+/// split the population into islands by spawn position,
+/// islands are vertical stripes of the world from left to right.
+///
+/// return the indices of blobs in each island
+fn assign_islands(
+    blob_vec: &[(Entity, (BlobGeno, BlobInfo))],
+    config: &SimConfig,
+) -> Vec<Vec<usize>> {
+    let mut order: Vec<usize> = (0..blob_vec.len()).collect();
+    order.sort_by(|&a, &b| {
+        let xa = blob_vec[a].1 .1.center_block_pos.x;
+        let xb = blob_vec[b].1 .1.center_block_pos.x;
+        xa.partial_cmp(&xb).unwrap_or(std::cmp::Ordering::Equal)
+    });
 
-/// logger function for swim training
-pub fn log_train_move_swim(frames: Res<Frames>, info_q: Query<&BlobInfo>, ted: Res<TED>) {
-    let cur_gen_frame_cnt = frames.0 % ITERATION_LENGTH as u128;
-    if cur_gen_frame_cnt != 0 || frames.0 == 0 {
-        return;
+    // population may differ from config when pressing `new_iteration` after loading
+    let mut islands = Vec::<Vec<usize>>::new();
+    let count = config.islands.count.clamp(1, blob_vec.len().max(1));
+    for island in 0..count {
+        let len = blob_vec.len() / count + usize::from(island < blob_vec.len() % count);
+        islands.push(order.drain(..len).collect());
     }
+    islands
+}
 
-    let mut infovec = Vec::from_iter(info_q.iter());
-
-    infovec.sort_by(|a, b| {
-        let a_distance_mag = a
-            .move_distance
-            .iter()
-            .fold(0.0, |acc, &x| acc + x * x)
-            .sqrt();
-        let b_distance_mag = b
-            .move_distance
-            .iter()
-            .fold(0.0, |acc, &x| acc + x * x)
-            .sqrt();
-        b_distance_mag.partial_cmp(&a_distance_mag).unwrap()
-    });
-
-    let top_distance = infovec[0]
-        .move_distance
-        .iter()
-        .fold(0.0, |acc, &x| acc + x * x)
-        .sqrt();
+/// move copies of the best survivers of each island to the next island (ring topology),
+/// replacing the worst non-elite survivers there.
+///
+/// return how many blobs migrated
+fn migrate(
+    island_survivers: &mut [Vec<(Entity, (BlobGeno, BlobInfo))>],
+    island_elites: &[usize],
+    score_of: impl Fn(Entity) -> f32,
+    migration_rate: f32,
+) -> usize {
+    let by_score = |survivers: &[(Entity, (BlobGeno, BlobInfo))]| {
+        let scores: Vec<f32> = survivers.iter().map(|(e, _)| score_of(*e)).collect();
+        let mut order: Vec<usize> = (0..survivers.len()).collect();
+        order.sort_by(|&a, &b| {
+            scores[b]
+                .partial_cmp(&scores[a])
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        order
+    };
 
-    let total_distances: f32 = infovec
+    // pick all migrants first, so that blobs only move one island each time
+    let migrants: Vec<Vec<(Entity, (BlobGeno, BlobInfo))>> = island_survivers
         .iter()
-        .map(|info| {
-            info.move_distance
+        .map(|survivers| {
+            let count = (survivers.len() as f32 * migration_rate).ceil() as usize;
+            by_score(survivers)
                 .iter()
-                .fold(0.0, |acc, &x| acc + x * x)
-                .sqrt()
+                .take(count)
+                .map(|&i| {
+                    // the original stays, the migrant is a new blob
+                    let (entity, (mut geno, info)) = survivers[i].clone();
+                    geno.lineage = Lineage::child_of(geno.lineage.id.into_iter().collect());
+                    (entity, (geno, info))
+                })
+                .collect()
         })
-        .sum();
+        .collect();
+
+    let len = island_survivers.len();
+    let mut count = 0;
+    for (from, migrants) in migrants.into_iter().enumerate() {
+        let to = (from + 1) % len;
+        let elite_len = island_elites[to];
+        let survivers = &mut island_survivers[to];
+        let worst: Vec<usize> = by_score(survivers)
+            .into_iter()
+            .rev()
+            .filter(|&i| i >= elite_len)
+            .take(migrants.len())
+            .collect();
+        for (i, migrant) in worst.into_iter().zip(migrants.into_iter()) {
+            survivers[i] = migrant;
+            count += 1;
+        }
+    }
+    count
+}
 
-    let mean_distance = total_distances / infovec.len() as f32;
+/// give blobs sharing nn_id with a blob before them their own copy of the NNs
+fn unshare_nn(genovec: &mut [BlobGeno], nnvec: &mut Vec<GenericNN>) {
+    let mut used = vec![false; nnvec.len()];
+    for geno in genovec.iter_mut() {
+        for nn_id in geno.all_nn_ids_mut() {
+            let id = nn_id.unwrap();
+            if used[id] {
+                nnvec.push(nnvec[id].clone());
+                *nn_id = Some(nnvec.len() - 1);
+            } else {
+                used[id] = true;
+            }
+        }
+    }
+}
 
+/// log objective values of the first Pareto front
+fn log_pareto_front(
+    iteration: u128,
+    names: &[Objective],
+    objectives: &[Vec<f32>],
+    front: &[usize],
+) {
+    let values: Vec<String> = front
+        .iter()
+        .map(|&i| {
+            let value: Vec<String> = objectives[i].iter().map(|v| format!("{:.5}", v)).collect();
+            format!("({})", value.join(", "))
+        })
+        .collect();
     logger_info!(
-        "iteration {}, top_distance {:.5}, mean_distance {:.5}, ted {:.5}",
-        frames.0 / ITERATION_LENGTH as u128,
-        top_distance,
-        mean_distance,
-        ted.0
+        "iteration {}, pareto front {:?}, {} blobs: {}",
+        iteration,
+        names,
+        front.len(),
+        values.join(" ")
     );
 }
 
-/// logger function for walk training
-pub fn log_train_move_walk(frames: Res<Frames>, info_q: Query<&BlobInfo>, ted: Res<TED>) {
-    let cur_gen_frame_cnt = frames.0 % ITERATION_LENGTH as u128;
+/// determin if iteration ends
+fn iteration_end(frames: Res<Frames>, config: &SimConfig) -> bool {
+    let cur_gen_frame_cnt = frames.0 % config.training.iteration_length as u128;
+    if cur_gen_frame_cnt == 0 && frames.0 != 0 {
+        true
+    } else {
+        false
+    }
+}
+
+/// logger function for training, log the top and mean fitness of current iteration
+pub fn log_train_move(
+    frames: Res<Frames>,
+    geno_info_q: Query<(&BlobGeno, &BlobInfo)>,
+    ted: Res<TED>,
+    config: Res<SimConfig>,
+    fitness: Res<TrainFitness>,
+) {
+    let iteration_length = config.training.iteration_length as u128;
+    let cur_gen_frame_cnt = frames.0 % iteration_length;
     if cur_gen_frame_cnt != 0 || frames.0 == 0 {
         return;
     }
 
-    let mut infovec = Vec::from_iter(info_q.iter());
+    let mut scores: Vec<f32> = geno_info_q
+        .iter()
+        .map(|(geno, info)| fitness.score(geno, info))
+        .collect();
 
-    // x axis move
-    infovec.sort_by(|a, b| {
-        let mag_a = a.move_distance[0];
-        let mag_b = b.move_distance[0];
-        mag_b
-            .partial_cmp(&mag_a)
+    scores.sort_by(|a, b| {
+        b.partial_cmp(a)
             .unwrap_or(std::cmp::Ordering::Equal)
     });
 
-    let top_x_distance = infovec[0]
-        .move_distance[0];
-
-    let total_x_distances: f32 = infovec
-    .iter()
-    .map(|info| {
-        info.move_distance[0]
-    })
-    .sum();
-
-    let mean_x_distance = total_x_distances / infovec.len() as f32;
+    let top_score = scores[0];
+    let mean_score = scores.iter().sum::<f32>() / scores.len() as f32;
 
     logger_info!(
-        "iteration {}, top_x_distance {:.5}, mean_x_distance {:.5}, ted {:.5}",
-        frames.0 / ITERATION_LENGTH as u128,
-        top_x_distance,
-        mean_x_distance,
-        ted.0
+        "iteration {}, top_{name} {:.5}, mean_{name} {:.5}, ted {:.5}",
+        frames.0 / iteration_length,
+        top_score,
+        mean_score,
+        ted.0,
+        name = fitness.name()
     );
 }
+#[cfg(test)]
+mod train_move_test {
+    use super::*;
+
+    /// blob with lineage `id` and entity index `id`, spawned at `x`
+    fn blob(id: u64, x: f32) -> (Entity, (BlobGeno, BlobInfo)) {
+        let mut geno = BlobGeno::default();
+        geno.lineage.id = Some(id);
+        let mut info = BlobInfo::default();
+        info.center_block_pos = Vec2::new(x, 0.0);
+        (Entity::from_raw(id as u32), (geno, info))
+    }
+
+    #[test]
+    fn test_assign_islands() {
+        let mut config = SimConfig::default();
+        config.islands.count = 3;
+        config.training.population = 30;
+        let blob_vec: Vec<_> = [5.0, -1.0, 3.0, 0.0, 4.0, 2.0, 1.0]
+            .into_iter()
+            .enumerate()
+            .map(|(i, x)| blob(i as u64, x))
+            .collect();
+
+        // stripes from left to right, the remainder goes to the first islands
+        let islands = assign_islands(&blob_vec, &config);
+        assert_eq!(islands, vec![vec![1, 3, 6], vec![5, 2], vec![4, 0]]);
+
+        // a loaded population smaller than the island count
+        let islands = assign_islands(&blob_vec[..2], &config);
+        assert_eq!(islands, vec![vec![1], vec![0]]);
+    }
+
+    #[test]
+    fn test_migrate() {
+        let scores: HashMap<u64, f32> = [
+            (0, 10.0),
+            (1, 9.0),
+            (2, 1.0),
+            (3, 0.0),
+            (4, 0.0),
+            (5, 5.0),
+            (6, 6.0),
+            (7, 7.0),
+        ]
+        .into_iter()
+        .collect();
+        let mut island_survivers: Vec<Vec<_>> = vec![
+            (0..4).map(|id| blob(id, 0.0)).collect(),
+            (4..8).map(|id| blob(id, 0.0)).collect(),
+        ];
+        // the first survivers are elites, even the weak one of the second island
+        let count = migrate(
+            &mut island_survivers,
+            &[1, 1],
+            |entity| scores[&(entity.index() as u64)],
+            0.25,
+        );
+        assert_eq!(count, 2);
+
+        let ids = |survivers: &[(Entity, (BlobGeno, BlobInfo))]| -> Vec<u32> {
+            survivers.iter().map(|(e, _)| e.index()).collect()
+        };
+        // the best of each island replaces the worst non-elite of the next one
+        assert_eq!(ids(&island_survivers[0]), vec![0, 1, 2, 7]);
+        assert_eq!(ids(&island_survivers[1]), vec![4, 0, 6, 7]);
+
+        // migrants are new blobs, the originals keep their ids
+        let migrant = &island_survivers[1][1].1 .0.lineage;
+        assert_eq!((migrant.id, migrant.parents.clone()), (None, vec![0]));
+        let migrant = &island_survivers[0][3].1 .0.lineage;
+        assert_eq!((migrant.id, migrant.parents.clone()), (None, vec![7]));
+        assert_eq!(island_survivers[0][0].1 .0.lineage.id, Some(0));
+        assert_eq!(island_survivers[1][3].1 .0.lineage.id, Some(7));
+    }
+}
@@ -5,8 +5,8 @@ use std::time::Instant;
 
 use bevy::prelude::*;
 use bevy_rapier2d::{
-    prelude::{Collider, ContactForceEvent, ImpulseJoint, Velocity},
-    rapier::prelude::JointAxis,
+    prelude::{Collider, ContactForceEvent, ImpulseJoint, RapierConfiguration, Velocity},
+    rapier::prelude::{JointAxis, JointMotor},
 };
 
 use crate::{
@@ -19,11 +19,16 @@ use crate::{
         resource::BevyBlockNeurons,
         signal::{BrainSignal, InwardNNInputSignal, SignalHandler},
     },
-    componet::{BlobEntityIndex, ColliderFlag},
-    consts::*,
+    config::{SimConfig, TrainingMode},
+    consts::DEFAULT_DENSITY,
+    physics::food::{smell, Food},
 };
 
-use super::resource::{Frames, TED};
+use super::{
+    resource::{Frames, TED},
+    touch::TouchSensor,
+    vision::VisionSensor,
+};
 
 /// **CORE FUNCTION**
 ///
@@ -35,16 +40,22 @@ use super::resource::{Frames, TED};
 /// Can not use `EventReader` multiple times each frame.
 /// Events been read will be marked as read.
 pub fn block_action(
-    mut brain_q: Query<(&Parent, Entity), With<CenterBlockFlag>>,
+    mut brain_q: Query<(&Parent, Entity, &Transform, &Velocity), With<CenterBlockFlag>>,
     mut block_q: Query<(Entity, &Parent, &mut ImpulseJoint)>,
     nn_id_q: Query<&NeuronId>,
     mut bbn: ResMut<BevyBlockNeurons>,
     mut cf_events: EventReader<ContactForceEvent>,
-    collider_q: Query<&ColliderFlag>,
+    touch: TouchSensor,
     joint_info_q: Query<&JointInfo>,
     depth_q: Query<&BlockDepth>,
     blob_q: Query<&BlobInfo>,
     p_anchor_q: Query<&ParentAnchor>,
+    parent_q: Query<&Parent>,
+    frames: Res<Frames>,
+    vision: VisionSensor,
+    rapier_config: Res<RapierConfiguration>,
+    food_q: Query<&Transform, With<Food>>,
+    config: Res<SimConfig>,
     // mut joint_q: Query<&mut ImpulseJoint>
 ) {
     let start_time = Instant::now();
@@ -60,7 +71,7 @@ pub fn block_action(
     }
 
     let mut signal_handler = SignalHandler::default();
-    let mut cf_events_vec = Vec::from_iter(cf_events.into_iter().cloned());
+    let touches = touch.collect(cf_events.iter());
 
     // push inward
     for (child, parent, joint) in block_q.iter_mut() {
@@ -76,7 +87,6 @@ pub fn block_action(
         });
 
         // init signal
-        let cf_singal = get_cf_signal(entity_id, &mut cf_events_vec, &collider_q);
         let joint_motor = joint.data.motor(JointAxis::AngX).unwrap();
         let joint_info = joint_info_q.get(entity_id).unwrap();
         let joint_signal = (
@@ -86,8 +96,9 @@ pub fn block_action(
             joint_info.ang_velocity,
         );
         let inward_signal = InwardNNInputSignal::default()
-            .with_cf_signal(cf_singal)
-            .with_joint_singal(joint_signal);
+            .with_touch(touches.get(&entity_id))
+            .with_joint_singal(joint_signal)
+            .with_vision(vision.sense(entity_id));
 
         // push inward signals to signal handler
         // unwarp parent_id, since all inward signal should have parent
@@ -104,20 +115,44 @@ pub fn block_action(
     }
 
     // push brains
-    for (parent, entity_id) in brain_q.iter_mut() {
+    for (parent, entity_id, transform, velocity) in brain_q.iter_mut() {
         // get id
         // should have id so unwrap
         let nn_id = nn_id_q.get(entity_id).unwrap().id;
-        // cf_signal
-        let cf_signal = get_cf_signal(entity_id, &mut cf_events_vec, &collider_q);
         // blob_signal
         // should in blobinfo so unwrap
         let blobinfo = blob_q.get(parent.get()).unwrap();
+        let (_, _, rotation) = transform.rotation.to_euler(EulerRot::XYZ);
+        let height = config
+            .ground_y()
+            .map_or(0.0, |ground| transform.translation.y - ground);
+        let smell_signal = (config.training.mode == TrainingMode::Forage).then(|| {
+            let foods = food_q.iter().map(|food| food.translation.truncate());
+            let (intensity, gradient) =
+                smell(transform.translation.truncate(), foods, config.food.smell_range);
+            let local = transform.rotation.inverse() * gradient.extend(0.0);
+            [intensity, local.x, local.y]
+        });
 
         signal_handler.push_brain(
             BrainSignal::default()
-                .with_cf_signal(cf_signal)
-                .with_blob_info(blobinfo.mass_center, blobinfo.velocity),
+                .with_touch(touches.get(&entity_id))
+                .with_blob_info(blobinfo.mass_center, blobinfo.velocity)
+                .with_orientation(
+                    rotation,
+                    velocity.angvel,
+                    rapier_config.gravity.to_array(),
+                    height,
+                )
+                .with_smell(smell_signal)
+                .with_energy(
+                    config
+                        .energy
+                        .enabled
+                        // eating can raise energy above the initial value
+                        .then(|| (blobinfo.energy / config.energy.initial).clamp(0.0, 1.0)),
+                )
+                .with_vision(vision.sense(entity_id)),
             nn_id,
         );
     }
@@ -125,17 +160,43 @@ pub fn block_action(
     // run neuron
     let output: Vec<(Entity, f32, f32)> = bbn.get_outputs(signal_handler);
 
+    if bbn.activity.is_enabled() {
+        // joint is the child of block, block is the child of blob
+        bbn.activity.commit(frames.0, |joint| {
+            let block = parent_q.get(joint).ok()?.get();
+            Some((block, parent_q.get(block).ok()?.get()))
+        });
+    }
+
+    // joint is the child of block, block is the child of blob
+    let exhausted = |joint: Entity| {
+        parent_q
+            .get(joint)
+            .and_then(|block| parent_q.get(block.get()))
+            .and_then(|blob| blob_q.get(blob.get()))
+            .map_or(false, |info| info.energy <= 0.0)
+    };
+
     // println!("{}",output[1].1);
     // update joints base on nn's output
     for (entity_id, target_pos, target_vel) in output {
         // println!("{},{}",target_pos,target_vel);
         let (_, _, mut joint) = block_q.get_mut(entity_id).unwrap();
+        // motors of exhausted blobs go limp
+        if config.energy.enabled && exhausted(entity_id) {
+            joint.data.set_motor(JointAxis::AngX, 0.0, 0.0, 0.0, 0.0);
+            continue;
+        }
+        let physics = &config.physics;
+        joint.data.set_motor_position(
+            JointAxis::AngX,
+            target_pos,
+            physics.motor_stiffness,
+            physics.motor_damping,
+        );
         joint
             .data
-            .set_motor_position(JointAxis::AngX, target_pos, MOTOR_STIFFNESS, MOTOR_DAMPING);
-        joint
-            .data
-            .set_motor_velocity(JointAxis::AngX, target_vel, MOTOR_DAMPING);
+            .set_motor_velocity(JointAxis::AngX, target_vel, physics.motor_damping);
     }
 
     // let output = bbn.get_rand_outputs(signal_handler);
@@ -150,65 +211,53 @@ pub fn block_action(
     //         .set_motor_velocity(JointAxis::AngX, signal[1], MOTOR_DAMPING);
     // }
     let duration = Instant::now() - start_time;
-    if PRINT_FUNCTION_TIME && duration >= MIN_PRINT_DURATION {
+    if config.debug.should_print(duration) {
         println!("block_action: {:?}", duration);
     }
 }
 
-// TODO: test preformance and change to `get_bulk_cf_events()` if necessary
-// loop over all entities over all events might be slow
-// TODO: Takes input of mut ref, returns a clone, considering return the reference
-// need to dealing with lifetime
-//
-/// get contact force event for an entity,
-/// return the first if multiple event shappen at the same time
-fn get_cf_event(
-    entity_id: Entity,
-    cf_events: &mut Vec<ContactForceEvent>,
-) -> Option<ContactForceEvent> {
-    cf_events
-        .iter()
-        .find(|&event| event.collider1 == entity_id || event.collider2 == entity_id)
-        .and_then(|event| Some(event.clone()))
-}
-
-/// Not a bevy system.
-///
-/// Output singal depends on NN's input
+/// Update `BlobInfo::energy` each frame, see `EnergyConfig`.
 ///
-/// collect contact force events and translate it into nn signals
-///
-/// contact blob and contact wall have different signal
-fn get_cf_signal(
-    entity_id: Entity,
-    cf_events_vec: &mut Vec<ContactForceEvent>,
-    blob_flag_q: &Query<&ColliderFlag>,
-) -> Option<(bool, bool, [f32; 2], f32)> {
-    // if contact
-    if let Some(event) = get_cf_event(entity_id, cf_events_vec) {
-        let other = if entity_id == event.collider1 {
-            event.collider2
-        } else {
-            event.collider1
-        };
-
-        if let (Ok(ColliderFlag::BLOCK(BlobEntityIndex(Some(sid)))), Ok(oflag)) =
-            (blob_flag_q.get(entity_id), blob_flag_q.get(other))
-        {
-            let (mut wall, mut blob, vect, mag) =
-                (false, false, event.total_force, event.total_force_magnitude);
-            if let ColliderFlag::WALL = oflag {
-                wall = true;
-            }
-            if let ColliderFlag::BLOCK(BlobEntityIndex(Some(oid))) = oflag {
-                if sid != oid {
-                    blob = true;
+/// blocks drain energy by their mass, joints by the work of their motors
+pub fn update_energy(
+    mut blob_q: Query<(&mut BlobInfo, &Children)>,
+    block_q: Query<(&Collider, &JointInfo, Option<&Children>)>,
+    joint_q: Query<&ImpulseJoint>,
+    config: Res<SimConfig>,
+) {
+    let energy = &config.energy;
+    if !energy.enabled {
+        return;
+    }
+    let dt = config.physics.rapier_dt;
+    for (mut blob, children) in blob_q.iter_mut() {
+        let mut cost = 0.0;
+        for (collider, joint_info, joints) in block_q.iter_many(children) {
+            let area = collider
+                .as_cuboid()
+                .map_or(0.0, |cuboid| 4.0 * cuboid.half_extents().x * cuboid.half_extents().y);
+            cost += energy.mass_cost * area * DEFAULT_DENSITY * dt;
+            // the joint to parent is a child of the block, center block has none
+            for joint in joint_q.iter_many(joints.into_iter().flatten()) {
+                if let Some(motor) = joint.data.motor(JointAxis::AngX) {
+                    cost += energy.motor_cost * motor_power(motor, joint_info) * dt;
                 }
             }
-            return Some((wall, blob, [vect.x, vect.y], mag));
         }
+        blob.spend(cost);
     }
-    None
+}
+
+/// mechanical power of a joint motor, `|torque * angular velocity|`.
+///
+/// torque follows the spring-damper model of rapier motors
+fn motor_power(motor: &JointMotor, joint_info: &JointInfo) -> f32 {
+    // `JointInfo` is in degrees
+    let pos = joint_info.ang_pos.to_radians();
+    let vel = joint_info.ang_velocity.to_radians();
+    let torque =
+        motor.stiffness * (motor.target_pos - pos) + motor.damping * (motor.target_vel - vel);
+    (torque * vel).abs()
 }
 
 /// Update `JointInfo` componet each frame.
@@ -221,6 +270,7 @@ pub fn update_joint_info(
     mut joint_info_q: Query<&mut JointInfo>,
     trans_q: Query<&Transform>,
     veloc_q: Query<&Velocity>,
+    config: Res<SimConfig>,
 ) {
     let start_time = Instant::now();
     for (parent, joint) in parent_joint_q.iter() {
@@ -247,7 +297,7 @@ pub fn update_joint_info(
         }
     }
     let duration = Instant::now() - start_time;
-    if PRINT_FUNCTION_TIME && duration >= MIN_PRINT_DURATION {
+    if config.debug.should_print(duration) {
         println!("update_joint_info: {:?}", duration);
     }
 }
@@ -302,6 +352,7 @@ pub fn get_relative_angular_velocity(v1: &Velocity, v2: &Velocity) -> f32 {
 /// - mass_center
 /// - velocity
 /// - cumulated move distance (for move training usage)
+/// - trajectory and joint stats (for novelty search usage)
 /// 
 /// # Panics
 ///
@@ -309,8 +360,10 @@ pub fn get_relative_angular_velocity(v1: &Velocity, v2: &Velocity) -> f32 {
 /// or if a blob does not have at least one block.
 pub fn update_blob_info(
     tc_q: Query<(&Transform, &Collider)>,
+    joint_info_q: Query<&JointInfo, Without<CenterBlockFlag>>,
     mut blob_q: Query<(&mut BlobInfo, &Children)>,
     frames: Res<Frames>,
+    config: Res<SimConfig>,
 ) {
     let start_time = Instant::now();
     for (mut blob, children) in blob_q.iter_mut() {
@@ -322,7 +375,12 @@ pub fn update_blob_info(
                 transform.translation.x,
                 transform.translation.y,
                 collider.scale().x * collider.scale().y,
-            ])
+            ]);
+            // `JointInfo` is on the block, the joint itself is a child entity,
+            // center block has no joint
+            if let Ok(ji) = joint_info_q.get(*child) {
+                blob.joint_stats.push(ji.ang_pos, ji.ang_velocity);
+            }
         }
         // unwrap since all blob should have at least one block
         let new_mass_center = get_mass_center(mass_vec).unwrap();
@@ -332,16 +390,28 @@ pub fn update_blob_info(
         ];
 
         // update move_distance
-        if frames.0 % ITERATION_LENGTH as u128 != 1 {
+        if frames.0 % config.training.iteration_length as u128 != 1 {
             blob.move_distance[0] += blob.velocity[0];
             blob.move_distance[1] += blob.velocity[1];
         }
 
         // update mass_center
         blob.mass_center = new_mass_center;
+
+        // sample the trajectory `trajectory_samples` times per iteration plus the last frame,
+        // restart it with the iteration so that it stays bounded in demo mode as well
+        let iteration_length = config.training.iteration_length.max(1) as u128;
+        let frame = (frames.0 + iteration_length - 1) % iteration_length;
+        let stride = (iteration_length / config.novelty.trajectory_samples.max(1) as u128).max(1);
+        if frame == 0 {
+            blob.trajectory.clear();
+        }
+        if frame % stride == 0 || frame == iteration_length - 1 {
+            blob.trajectory.push(new_mass_center);
+        }
     }
     let duration = Instant::now() - start_time;
-    if PRINT_FUNCTION_TIME && duration >= MIN_PRINT_DURATION {
+    if config.debug.should_print(duration) {
         println!("update_blob_info: {:?}", duration);
     }
 }
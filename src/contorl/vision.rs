@@ -0,0 +1,144 @@
+//! raycast vision of blocks
+//!
+//! Each block casts a fan of rays from its free face (the one opposite to its parent),
+//! the brain looks from its top face.
+//! Every ray gives `VISION_RAY_LEN` inputs: closeness in `[0, 1]` and a one-hot hit type,
+//! all zero if nothing is hit within `VisionConfig::range`.
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_rapier2d::prelude::{QueryFilter, RapierContext};
+
+use crate::{
+    blob::block::{BlockAnchors, ParentAnchor},
+    componet::{BlobEntityIndex, ColliderFlag},
+    config::{SimConfig, VisionConfig},
+    consts::VISION_RAY_LEN,
+    physics::world::Ground,
+};
+
+/// what a ray hits, the value is its one-hot index after closeness
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HitType {
+    Wall = 0,
+    OwnBlob = 1,
+    OtherBlob = 2,
+    Food = 3,
+    Terrain = 4,
+}
+
+/// queries needed to cast rays, bundled to keep `block_action` under the param limit
+#[derive(SystemParam)]
+pub struct VisionSensor<'w, 's> {
+    rapier_context: Res<'w, RapierContext>,
+    config: Res<'w, SimConfig>,
+    block_q: Query<
+        'w,
+        's,
+        (
+            &'static Transform,
+            &'static BlockAnchors,
+            &'static ParentAnchor,
+        ),
+    >,
+    flag_q: Query<'w, 's, &'static ColliderFlag>,
+    ground_q: Query<'w, 's, (), With<Ground>>,
+}
+
+impl VisionSensor<'_, '_> {
+    /// vision inputs of `block`, empty if vision is disabled
+    pub fn sense(&self, block: Entity) -> Vec<f32> {
+        let vision = &self.config.vision;
+        if !vision.enabled {
+            return Vec::new();
+        }
+        let mut inputs = vec![0.0; vision.input_len()];
+        let Ok((transform, anchors, parent_anchor)) = self.block_q.get(block) else {
+            return inputs;
+        };
+
+        let face = anchors.of(parent_anchor.0.unwrap_or(0));
+        let origin = transform.translation.truncate() + rotate(transform, face);
+        let facing = rotate(transform, face.normalize_or_zero());
+        let filter = QueryFilter::default().exclude_collider(block);
+        for (dir, ray) in ray_dirs(facing, vision).zip(inputs.chunks_mut(VISION_RAY_LEN)) {
+            if let Some((entity, toi)) =
+                self.rapier_context
+                    .cast_ray(origin, dir, vision.range, true, filter)
+            {
+                if let Some(hit) = self.hit_type(block, entity) {
+                    encode(ray, toi / vision.range, hit);
+                }
+            }
+        }
+        inputs
+    }
+
+    fn hit_type(&self, block: Entity, other: Entity) -> Option<HitType> {
+        if self.ground_q.contains(other) {
+            return Some(HitType::Terrain);
+        }
+        match (self.flag_q.get(block).ok()?, self.flag_q.get(other).ok()?) {
+            (_, ColliderFlag::WALL) => Some(HitType::Wall),
+            (_, ColliderFlag::FOOD) => Some(HitType::Food),
+            (
+                ColliderFlag::BLOCK(BlobEntityIndex(sid)),
+                ColliderFlag::BLOCK(BlobEntityIndex(oid)),
+            ) => {
+                if sid == oid {
+                    Some(HitType::OwnBlob)
+                } else {
+                    Some(HitType::OtherBlob)
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+fn rotate(transform: &Transform, v: Vec2) -> Vec2 {
+    (transform.rotation * v.extend(0.0)).truncate()
+}
+
+/// directions of the rays, spread evenly over `fov` around `facing`
+pub fn ray_dirs(facing: Vec2, vision: &VisionConfig) -> impl Iterator<Item = Vec2> + '_ {
+    (0..vision.rays).map(move |i| {
+        let angle = if vision.rays > 1 {
+            vision.fov * (i as f32 / (vision.rays - 1) as f32 - 0.5)
+        } else {
+            0.0
+        };
+        Vec2::from_angle(angle).rotate(facing)
+    })
+}
+
+/// write a hit at `distance` (ratio of range) into the inputs of a ray
+pub fn encode(ray: &mut [f32], distance: f32, hit: HitType) {
+    ray[0] = 1.0 - distance.clamp(0.0, 1.0);
+    ray[1 + hit as usize] = 1.0;
+}
+
+#[cfg(test)]
+mod vision_test {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    #[test]
+    fn test_ray_dirs() {
+        let vision = VisionConfig {
+            enabled: true,
+            rays: 3,
+            fov: PI / 2.0,
+            range: 100.0,
+        };
+        let dirs: Vec<Vec2> = ray_dirs(Vec2::Y, &vision).collect();
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        assert!(dirs[0].abs_diff_eq(Vec2::new(half, half), 1e-5));
+        assert!(dirs[1].abs_diff_eq(Vec2::Y, 1e-5));
+        assert!(dirs[2].abs_diff_eq(Vec2::new(-half, half), 1e-5));
+
+        let mut ray = [0.0; VISION_RAY_LEN];
+        encode(&mut ray, 0.25, HitType::OtherBlob);
+        assert_eq!(ray, [0.75, 0.0, 0.0, 1.0, 0.0, 0.0]);
+    }
+}
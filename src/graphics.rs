@@ -2,11 +2,10 @@
 
 use bevy::{prelude::*, window::PresentMode};
 use bevy_pancam::{PanCam, PanCamPlugin};
-use bevy_rapier2d::prelude::{RapierConfiguration, TimestepMode};
 
 // use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
 
-use crate::consts::{AUTO_NO_VSYNC_KEYCODE, RAPIER_DT, RAPIER_SUBSTEPS};
+use crate::config::SimConfig;
 
 #[derive(Component)]
 pub struct MainCamera;
@@ -16,7 +15,6 @@ pub struct MainCamera;
 /// includes
 /// - camera & camera contorl
 /// - vsgnc & novsync
-/// - time step contorl
 pub struct EvoGraphicsPlugin;
 
 impl Plugin for EvoGraphicsPlugin {
@@ -28,15 +26,7 @@ impl Plugin for EvoGraphicsPlugin {
                 // LogDiagnosticsPlugin::default(),
                 // FrameTimeDiagnosticsPlugin::default(),
             ))
-            .add_systems(Update, toggle_vsync)
-            // using Fixed timestep so that the simulation can speed up
-            .insert_resource(RapierConfiguration {
-                timestep_mode: TimestepMode::Fixed {
-                    dt: RAPIER_DT,
-                    substeps: RAPIER_SUBSTEPS,
-                },
-                ..default()
-            });
+            .add_systems(Update, toggle_vsync);
     }
 
     
@@ -48,8 +38,12 @@ pub fn setup_graphics(mut commands: Commands) {
 }
 
 /// This system toggles the vsync mode when pressing the button.
-fn toggle_vsync(input: Res<Input<KeyCode>>, mut windows: Query<&mut Window>) {
-    if input.just_pressed(AUTO_NO_VSYNC_KEYCODE) {
+fn toggle_vsync(
+    input: Res<Input<KeyCode>>,
+    mut windows: Query<&mut Window>,
+    config: Res<SimConfig>,
+) {
+    if input.just_pressed(config.keys.auto_no_vsync) {
         let mut window = windows.single_mut();
 
         window.present_mode = if matches!(window.present_mode, PresentMode::AutoVsync) {
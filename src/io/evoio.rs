@@ -4,7 +4,7 @@ use bevy::prelude::*;
 
 use crate::contorl::update::block_action;
 
-use super::{export::export, import::{load_blobs, clean}};
+use super::{export::{export, export_activity}, import::{load_blobs, clean}};
 
 /// all implementations relate to import and export (save and load)
 /// 
@@ -20,6 +20,7 @@ impl Plugin for EvoIOPlugin {
         app
         .add_systems(Update, (
             export,
+            export_activity,
             clean.after(block_action),
             load_blobs.after(clean),
         ))
@@ -9,13 +9,13 @@ use serde::{Serialize, Deserialize};
 use chrono::{Local, NaiveDateTime, Datelike, Timelike};
 
 use crate::blob::blob::BlobInfo;
-use crate::consts::{SAVE_ALL_BLOBS_TO_JSON, ITERATION_LENGTH, CHECKPOINTS_LENGTH};
-use crate::contorl::resource::Frames;
+use crate::config::{config, SimConfig};
+use crate::contorl::{fitness::TrainFitness, resource::Frames};
 use crate::logger_info;
+use crate::rng::SimRng;
 use crate::{
     blob::{block::NeuronId, geno_blob_builder::BlobGeno},
     brain::{resource::BevyBlockNeurons, neuron::GenericNN},
-    consts::EXPORT_PATH,
 };
 
 /// struct for file to save & load
@@ -26,21 +26,30 @@ pub struct ExportFile{
     genovec: Vec<BlobGeno>,
     /// nested vec, outer relate to blob, inner relate to block (blob's limb)
     nnvec: Vec<Vec<(GenericNN,usize)>>,
-    posvec: Vec<[f32;2]>
+    posvec: Vec<[f32;2]>,
+    /// fitness score of each blob when saved, empty for old files
+    #[serde(default)]
+    fitnessvec: Vec<f32>,
+    /// seed of the run that saved the file, `None` for old files
+    #[serde(default)]
+    seed: Option<u64>
 }
 
 impl ExportFile {
-    fn new() -> Self {
+    fn new(seed: u64) -> Self {
         Self{
             genovec: Vec::<BlobGeno>::new(),
             nnvec: Vec::<Vec<(GenericNN,usize)>>::new(),
-            posvec: Vec::<[f32;2]>::new()
+            posvec: Vec::<[f32;2]>::new(),
+            fitnessvec: Vec::<f32>::new(),
+            seed: Some(seed)
         }
     }
 
-    pub fn push_blob(&mut self, blob: (&BlobGeno,&BlobInfo)){
+    pub fn push_blob(&mut self, blob: (&BlobGeno,&BlobInfo), fitness: f32){
         self.genovec.push(blob.0.clone());
         self.posvec.push(blob.1.center_block_pos.into());
+        self.fitnessvec.push(fitness);
     }
 
     pub fn push_nn(&mut self, nnvec: Vec<(GenericNN,usize)>){
@@ -51,11 +60,11 @@ impl ExportFile {
         assert_eq!(self.genovec.len(),self.nnvec.len());
         assert_eq!(self.genovec.len(),self.posvec.len());
         let file_str = serde_json::to_string(&self).unwrap();
-        let fname = format!("{}{}",EXPORT_PATH,current_time_filename());
+        let fname = Path::new(&config().io.export_path).join(current_time_filename());
         let mut file = File::create(&fname).expect("Unable to create file");
         file.write_all(file_str.as_bytes()).expect("Unable to write data");
-        info!("MODEL SAVED {}", &fname);
-        logger_info!("MODEL SAVED {}", &fname);
+        info!("MODEL SAVED {}", fname.display());
+        logger_info!("MODEL SAVED {}, seed {:?}", fname.display(), self.seed);
     }
 
     pub fn len(&self) -> usize{
@@ -79,9 +88,32 @@ impl ExportFile {
         }
     }
 
-    pub fn check(&self) {
-        assert_eq!(self.genovec.len(),self.nnvec.len());
-        assert_eq!(self.genovec.len(),self.posvec.len());
+    /// all vectors describe the same blobs
+    pub fn check(&self) -> Result<(), String> {
+        let len = self.genovec.len();
+        if self.nnvec.len() != len || self.posvec.len() != len {
+            return Err(format!(
+                "{} genos, {} NN groups and {} positions do not match",
+                len,
+                self.nnvec.len(),
+                self.posvec.len()
+            ));
+        }
+        if !self.fitnessvec.is_empty() && self.fitnessvec.len() != len {
+            return Err(format!("{} genos but {} fitness values", len, self.fitnessvec.len()));
+        }
+        Ok(())
+    }
+
+    /// migrate NNs saved with other shapes to the ones in `config`,
+    /// return the number of migrated NNs
+    pub fn migrate(&mut self, config: &SimConfig) -> usize {
+        self.nnvec
+            .iter_mut()
+            .flatten()
+            .map(|(nn, _)| nn.migrate(config))
+            .filter(|&migrated| migrated)
+            .count()
     }
 
     /// Flattening and sorting by usize index, return cloned nnvec
@@ -131,19 +163,22 @@ pub fn export(
     blob_q: Query<(Entity, (&BlobGeno, &BlobInfo))>,
     nn_q: Query<(&Parent, &NeuronId)>,
     bbn: Res<BevyBlockNeurons>,
-    frames: Res<Frames>
+    frames: Res<Frames>,
+    config: Res<SimConfig>,
+    fitness: Res<TrainFitness>,
+    rng: Res<SimRng>
 ) {
     if blob_q.is_empty() || nn_q.is_empty() {
         return;
     }
 
-    if input.just_pressed(SAVE_ALL_BLOBS_TO_JSON) || is_checkpoints(frames){
-        create_if_not_exist();
-        let mut ef = ExportFile::new();
+    if input.just_pressed(config.keys.save_all_blobs_to_json) || is_checkpoints(frames, &config){
+        create_if_not_exist(&config.io.export_path);
+        let mut ef = ExportFile::new(rng.seed());
         let nnvec = &bbn.nnvec;
 
         for (blob_id, blob) in blob_q.iter(){
-            ef.push_blob(blob);
+            ef.push_blob(blob, fitness.score(blob.0, blob.1));
             let mut blob_nn = Vec::<(GenericNN,usize)>::new();
             for (parent_id, neuron) in nn_q.iter(){
                 if parent_id.get() != blob_id {
@@ -158,9 +193,7 @@ pub fn export(
     }
 }
 
-fn create_if_not_exist() {
-    let path = EXPORT_PATH;
-
+fn create_if_not_exist(path: &str) {
     // Check if the path exists
     if !Path::new(path).exists() {
         // Create the directory if it doesn't exist
@@ -172,17 +205,49 @@ fn create_if_not_exist() {
 }
 
 fn current_time_filename() -> String {
+    format!("{}.json", current_time_stem())
+}
+
+fn current_time_stem() -> String {
     let now: NaiveDateTime = Local::now().naive_local();
-    format!("{:04}-{:02}-{:02}T{:02}-{:02}-{:02}.json",
+    format!("{:04}-{:02}-{:02}T{:02}-{:02}-{:02}",
             now.year(), now.month(), now.day(),
             now.hour(), now.minute(), now.second())
 }
 
-fn is_checkpoints(frames: Res<Frames>) -> bool {
-    let cur_frame = frames.0 % ITERATION_LENGTH as u128;
-    let iterations = frames.0 / ITERATION_LENGTH as u128;
-    let cur_cp_iter_num = iterations % CHECKPOINTS_LENGTH as u128;
-    if cur_cp_iter_num == 0 && iterations != 0 && cur_frame == 0{
+/// dump the recorded NN activity of all blobs to a csv file
+pub fn export_activity(
+    input: Res<Input<KeyCode>>,
+    bbn: Res<BevyBlockNeurons>,
+    config: Res<SimConfig>,
+) {
+    if !input.just_pressed(config.keys.dump_activity) {
+        return;
+    }
+    if !bbn.activity.is_enabled() {
+        warn!("activity is not recorded, set debug.record_activity to enable it");
+        return;
+    }
+
+    let fname = Path::new(&config.io.export_path)
+        .join(format!("activity-{}.csv", current_time_stem()));
+    match bbn.activity.dump(None, 0..u128::MAX, &fname) {
+        Ok(_) => {
+            logger_info!("ACTIVITY SAVED {}", fname.display());
+        }
+        Err(e) => warn!("Failed to save {}: {}", fname.display(), e),
+    }
+}
+
+/// checkpoints are saved every `checkpoints_length` iterations,
+/// and at the last generation if `max_generations` is set
+fn is_checkpoints(frames: Res<Frames>, config: &SimConfig) -> bool {
+    let iteration_length = config.training.iteration_length as u128;
+    let cur_frame = frames.0 % iteration_length;
+    let iterations = frames.0 / iteration_length;
+    let cur_cp_iter_num = iterations % config.training.checkpoints_length as u128;
+    let is_last = config.training.max_generations.map(|n| n as u128) == Some(iterations);
+    if (cur_cp_iter_num == 0 || is_last) && iterations != 0 && cur_frame == 0{
         true
     } else {
         false
@@ -1,18 +1,21 @@
 //! Import and deserialize the checkpoint or exported files
 
-use std::fs::{File, self};
-use std::io::Read;
+use std::error::Error;
+use std::fs;
 
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::ImpulseJoint;
+use rand::rngs::StdRng;
 use serde_json;
 
 use crate::blob::blob::Blob;
 use crate::blob::geno_blob_builder::GenoBlobBuilder;
-use crate::brain::resource::BevyBlockNeurons;
-use crate::componet::ColliderFlag;
-use crate::consts::*;
-use crate::physics::world::Wall;
+use crate::brain::{neat::Innovations, resource::BevyBlockNeurons};
+use crate::componet::BlobColliderFilter;
+use crate::config::{config, SimConfig};
+use crate::consts::{HALL_OF_FAME_FNAME, MAP_ELITES_FNAME};
+use crate::logger_info;
+use crate::rng::SimRng;
 
 use super::export::ExportFile;
 
@@ -21,44 +24,48 @@ pub fn load_blobs(
     commands: Commands,
     mut bbn: ResMut<BevyBlockNeurons>,
     input: Res<Input<KeyCode>>,
+    config: Res<SimConfig>,
+    mut innovations: ResMut<Innovations>,
+    mut rng: ResMut<SimRng>,
 ) {
-    let mut load_fname = LOAD_FNAME.to_string();
-    if LOAD_NEWEST_FILE {
-        let path = newest_file_name_in_directory(LOAD_FOLDER);
+    if !input.just_pressed(config.keys.load_all_blobs_from_json) {
+        return;
+    }
+
+    let io = &config.io;
+    let mut load_fname = io.load_fname.clone();
+    if io.load_newest_file {
+        let path = newest_file_name_in_directory(&io.load_folder);
         if let Some(path) = path {
-            load_fname = LOAD_FOLDER.to_string() + &path;
+            load_fname = io.load_folder.clone() + &path;
         } else {
-            panic!("empty load folder")
+            warn!("empty load folder {}", io.load_folder);
+            return;
         }
     }
-    
-    if input.just_pressed(LOAD_ALL_BLOBS_FROM_JSON) {
-        match File::open(&load_fname) {
-            Ok(mut file) => {
-                let mut file_str = String::new();
-
-                // Handle read_to_string error
-                if let Err(e) = file.read_to_string(&mut file_str) {
-                    warn!("Failed to read from file {}: {:?}", load_fname, e);
-                    return;
-                }
-
-                // Handle serde_json parsing error
-                match serde_json::from_str::<ExportFile>(&file_str) {
-                    Ok(ef) => {
-                        ef.check();
-                        overwrite(ef, commands, &mut bbn);
-                    }
-                    Err(e) => {
-                        warn!("Failed to parse the file content as `ExportFile`: {:?}", e);
-                    }
-                }
-            }
-            Err(e) => {
-                warn!("Failed to open file {}: {:?}", LOAD_FNAME, e);
-            }
-        }
+
+    match read_population_file(&load_fname, config.training.population) {
+        Ok(ef) => overwrite(ef, commands, &mut bbn, &mut innovations, &mut rng.nn),
+        Err(e) => warn!("Failed to load file {}: {:?}", load_fname, e),
+    }
+}
+
+/// read and deserialize an exported file or checkpoints file
+pub fn read_export_file(path: &str) -> Result<ExportFile, Box<dyn Error>> {
+    let file_str = fs::read_to_string(path)?;
+    let ef = serde_json::from_str::<ExportFile>(&file_str)?;
+    ef.check()?;
+    Ok(ef)
+}
+
+/// read an exported file to replace the population,
+/// which can not have more blobs than `population`
+pub fn read_population_file(path: &str, population: usize) -> Result<ExportFile, Box<dyn Error>> {
+    let ef = read_export_file(path)?;
+    if ef.len() > population {
+        return Err(format!("{} blobs, but population is {}", ef.len(), population).into());
     }
+    Ok(ef)
 }
 
 
@@ -69,11 +76,13 @@ pub fn clean(
     mut commands: Commands,
     mut bbn: ResMut<BevyBlockNeurons>,
     blob_q: Query<Entity, With<Blob>>,
-    collider_q: Query<Entity, (With<ColliderFlag>, Without<Wall>)>,
+    collider_q: Query<Entity, BlobColliderFilter>,
     joint_q: Query<Entity, With<ImpulseJoint>>,
     input: Res<Input<KeyCode>>,
+    config: Res<SimConfig>,
 ) {
-    if input.just_pressed(LOAD_ALL_BLOBS_FROM_JSON) || input.just_pressed(CLEAN_ALL_BLOBS_KEYCODE) {
+    let keys = &config.keys;
+    if input.just_pressed(keys.load_all_blobs_from_json) || input.just_pressed(keys.clean_all_blobs) {
         for entity in blob_q.iter().chain(collider_q.iter()).chain(joint_q.iter()) {
             commands.entity(entity).despawn()
         }
@@ -82,8 +91,22 @@ pub fn clean(
 }
 
 /// ignore and overwrite all blobs and NNs that exist
-fn overwrite(mut ef: ExportFile, commands: Commands, bbn: &mut BevyBlockNeurons) {
-    let mut builder = GenoBlobBuilder::from_commands(commands, &mut bbn.nnvec);
+///
+/// NNs saved with other shapes are migrated to the configured ones,
+/// see `read_population_file` for the population limit
+pub fn overwrite(
+    mut ef: ExportFile,
+    commands: Commands,
+    bbn: &mut BevyBlockNeurons,
+    innovations: &mut Innovations,
+    rng: &mut StdRng,
+) {
+    let migrated = ef.migrate(config());
+    if migrated > 0 {
+        logger_info!("{} NNs migrated to the configured shape", migrated);
+    }
+
+    let mut builder = GenoBlobBuilder::from_commands(commands, &mut bbn.nnvec, innovations, rng);
 
     // build loaded blobs
     for (geno, pos, _nnvec) in ef.iter_mut() {
@@ -94,6 +117,10 @@ fn overwrite(mut ef: ExportFile, commands: Commands, bbn: &mut BevyBlockNeurons)
 
     // set resource
     bbn.nnvec = ef.flatten_nnvec();
+    // innovation numbers are not saved, continue after the loaded ones
+    for nn in bbn.nnvec.iter() {
+        nn.observe_innovations(innovations);
+    }
 }
 
 /// take folder path as input, return fname
@@ -102,7 +129,12 @@ fn newest_file_name_in_directory(dir: &str) -> Option<String> {
         .ok()?
         .filter_map(|entry| {
             let entry = entry.ok()?;
-            if entry.path().extension()? == "json" {
+            // hall of fame and map elites are not exported files
+            let fname = entry.file_name();
+            if entry.path().extension()? == "json"
+                && fname.to_str()? != HALL_OF_FAME_FNAME
+                && fname.to_str()? != MAP_ELITES_FNAME
+            {
                 entry.path().file_name()?.to_str().map(String::from)
             } else {
                 None
@@ -0,0 +1,172 @@
+//! lineage file and the DOT exporter of the ancestry tree
+//!
+//! The lineage file has one JSON `LineageRecord` per line,
+//! each evaluated blob adds one record per generation.
+
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fmt::Write as _,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::blob::lineage::MutationEvent;
+
+/// a blob evaluated in a generation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LineageRecord {
+    pub generation: u128,
+    pub id: u64,
+    pub parents: Vec<u64>,
+    pub fitness: f32,
+    /// mutations applied before this evaluation
+    pub mutations: Vec<MutationEvent>,
+}
+
+/// append records to the lineage file, create it if not exists.
+///
+/// truncate the file first if `truncate` is set
+pub fn write_records(
+    path: &Path,
+    records: &[LineageRecord],
+    truncate: bool,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(folder) = path.parent() {
+        fs::create_dir_all(folder)?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(!truncate)
+        .truncate(truncate)
+        .open(path)?;
+    let mut lines = String::new();
+    for record in records.iter() {
+        lines.push_str(&serde_json::to_string(record)?);
+        lines.push('\n');
+    }
+    file.write_all(lines.as_bytes())?;
+    Ok(())
+}
+
+pub fn read_records(path: &str) -> Result<Vec<LineageRecord>, Box<dyn Error>> {
+    let file_str = fs::read_to_string(path)?;
+    let mut records = Vec::<LineageRecord>::new();
+    for line in file_str.lines().filter(|line| !line.trim().is_empty()) {
+        records.push(serde_json::from_str(line)?);
+    }
+    Ok(records)
+}
+
+/// ancestry tree in DOT format.
+///
+/// Each blob is a node with its id, generations alive, best fitness and mutation count,
+/// edges point from parents to children
+pub fn to_dot(records: &[LineageRecord]) -> String {
+    struct Node {
+        parents: Vec<u64>,
+        first: u128,
+        last: u128,
+        best: f32,
+        mutations: usize,
+    }
+
+    let mut nodes = BTreeMap::<u64, Node>::new();
+    for record in records.iter() {
+        let node = nodes.entry(record.id).or_insert(Node {
+            parents: record.parents.clone(),
+            first: record.generation,
+            last: record.generation,
+            best: record.fitness,
+            mutations: 0,
+        });
+        node.first = node.first.min(record.generation);
+        node.last = node.last.max(record.generation);
+        node.best = node.best.max(record.fitness);
+        node.mutations += record.mutations.len();
+    }
+
+    let mut dot = String::new();
+    dot.push_str(
+        r#"digraph {
+
+    graph [
+        label="evosim lineage",
+        labelloc=t,
+
+        pad=0.4,
+        rankdir=TB,
+
+        fontname="Helvetica",
+        fontsize="36",
+    ];
+
+    node [
+        fontname="monospace",
+        fontsize="10",
+        shape="record",
+        style="filled",
+    ];
+
+    edge [
+        fontname="monospace",
+        fontsize="10",
+    ];
+
+"#,
+    );
+
+    for (id, node) in nodes.iter() {
+        // crossover children have a different color
+        let color = if node.parents.len() > 1 {
+            "#f8c04c"
+        } else {
+            "#81c169"
+        };
+        writeln!(
+            dot,
+            "    \"{}\" [label=\"blob {}|gen {}-{}|best {:.3}|{} mutations\", fillcolor=\"{}\"];",
+            id, id, node.first, node.last, node.best, node.mutations, color
+        )
+        .unwrap();
+    }
+    dot.push('\n');
+    for (id, node) in nodes.iter() {
+        for parent in node.parents.iter() {
+            writeln!(dot, "    \"{}\" -> \"{}\";", parent, id).unwrap();
+        }
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+#[cfg(test)]
+mod lineage_test {
+    use super::*;
+
+    #[test]
+    fn test_to_dot() {
+        let record = |generation, id, parents: Vec<u64>, fitness| LineageRecord {
+            generation,
+            id,
+            parents,
+            fitness,
+            mutations: vec![MutationEvent::Nn { blocks: 1 }],
+        };
+        let records = vec![
+            record(0, 1, vec![], 1.0),
+            record(0, 2, vec![], 2.0),
+            record(1, 1, vec![], 3.0),
+            record(1, 3, vec![1, 2], 0.5),
+        ];
+        let dot = to_dot(&records);
+        assert!(dot.contains("\"1\" [label=\"blob 1|gen 0-1|best 3.000|2 mutations\""));
+        assert!(dot.contains("\"1\" -> \"3\";"));
+        assert!(dot.contains("\"2\" -> \"3\";"));
+        assert!(!dot.contains("-> \"1\""));
+    }
+}
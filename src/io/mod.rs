@@ -2,4 +2,5 @@
 
 pub mod export;
 pub mod import;
+pub mod lineage;
 pub mod evoio;
\ No newline at end of file
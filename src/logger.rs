@@ -5,14 +5,14 @@ use chrono::Local;
 use std::fs::OpenOptions;
 use std::io::Write;
 
-use crate::consts::LOG_PATH;
+use crate::config::config;
 
 pub fn log_to_file(level: &str, message: &str) {
     let mut file = OpenOptions::new()
         .create(true)
         .write(true)
         .append(true)
-        .open(LOG_PATH)
+        .open(&config().io.log_path)
         .unwrap();
 
     let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
@@ -2,55 +2,106 @@
 
 mod blob;
 mod brain;
+mod cli;
 mod componet;
+mod config;
 mod consts;
 mod contorl;
 mod graphics;
 mod io;
 mod mutate;
 mod physics;
+mod rng;
 
 #[macro_use]
 mod logger;
 
-use bevy::prelude::*;
+use std::{process::ExitCode, sync::atomic::Ordering, time::Duration};
 
-use brain::resource::BevyBlockNeurons;
-use contorl::contorl::BlobContorlPlugin;
+use bevy::{app::ScheduleRunnerPlugin, input::InputPlugin, log::LogPlugin, prelude::*};
+use clap::Parser;
+
+use brain::{neat::Innovations, resource::BevyBlockNeurons};
+use cli::{Cli, Command};
+use config::init_config;
+use contorl::contorl::{BlobContorlPlugin, RUN_FAILED};
 use graphics::*;
 use io::evoio::EvoIOPlugin;
 use mutate::mutate::MutatePlugin;
 use physics::physical_world::PhysiWorldPlugin;
+use rng::SimRng;
 
-// TODO: Headless mode causing panic
 // TODO: Not all cores are fully tuilized
 /// Main function to start the simulation (which is a bevy app)
-fn main() {
-    App::new()
-        .add_plugins((
+///
+/// run `evosim --help` for the command-line options
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    // tools that don't run the simulation
+    if let Some(Command::Lineage(args)) = &cli.command {
+        return match args.run() {
+            Ok(_) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let config = match cli.to_config() {
+        Ok(config) => init_config(config),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // set thread count, default is automatic
+    let mut task_pool_options = TaskPoolOptions::default();
+    if let Some(thread_count) = config.thread_count {
+        task_pool_options = TaskPoolOptions::with_num_threads(thread_count);
+    }
+
+    let mut app = App::new();
+    app.insert_resource(config.clone())
+        .insert_resource(SimRng::from_config(config));
+
+    if config.headless {
+        app.add_plugins((
+            // no window and renderer,
+            // run the next frame as soon as the last one is done
+            MinimalPlugins
+                .set(TaskPoolPlugin { task_pool_options })
+                .set(ScheduleRunnerPlugin::run_loop(Duration::ZERO)),
+            LogPlugin::default(),
+            TransformPlugin,
+            HierarchyPlugin,
+            // keyboard resources are still used by the contorl systems
+            InputPlugin,
+        ));
+    } else {
+        app.add_plugins((
             // defualt
-            DefaultPlugins,
-            // // set thread count
-            // DefaultPlugins.set(
-            //     TaskPoolPlugin{
-            //         task_pool_options: TaskPoolOptions::with_num_threads(THREAD_COUNT)
-            //     }
-            // ),
-            // // no renderer
-            // DefaultPlugins.set(RenderPlugin {
-            //     wgpu_settings: WgpuSettings {
-            //         backends: None,
-            //         ..default()
-            //     }
-            // }),
-
-            // custom
-            PhysiWorldPlugin,  // init physical world
+            DefaultPlugins.set(TaskPoolPlugin { task_pool_options }),
             EvoGraphicsPlugin, // vsync and camera
-            EvoIOPlugin,       // import and export
-            MutatePlugin,      // mutation contorl
-            BlobContorlPlugin, // update blob each frame
-        ))
-        .init_resource::<BevyBlockNeurons>()
-        .run();
+        ));
+    }
+
+    app.add_plugins((
+        // custom
+        PhysiWorldPlugin,  // init physical world
+        EvoIOPlugin,       // import and export
+        MutatePlugin,      // mutation contorl
+        BlobContorlPlugin, // update blob each frame
+    ))
+    .init_resource::<BevyBlockNeurons>()
+    // movement training inserts its own, with the genes of loaded archives
+    .init_resource::<Innovations>()
+    .run();
+
+    if RUN_FAILED.load(Ordering::Relaxed) {
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
 }
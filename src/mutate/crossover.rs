@@ -0,0 +1,295 @@
+//! implementation of crossover (recombination) between two parent blobs
+
+use std::collections::HashMap;
+
+use ndarray::Zip;
+use rand::prelude::*;
+
+use crate::{
+    blob::geno_blob_builder::{BlobGeno, GenericGenoNode},
+    brain::{
+        neat::NeatNN,
+        neuron::{AnyNN, GenericNN},
+        nn::BaseNN,
+    },
+};
+
+/// crossover two genos by replacing a subtree of `geno` with the subtree
+/// at the same position of `mate`.
+///
+/// The subtree is moved so that it attaches to the parent block of `geno`,
+/// and carries its `nn_id` so that the NNs of `mate` come along.
+///
+/// Returns the child and the `(child_nn_id, mate_nn_id)` pairs of blocks both parents share,
+/// which can be used for weight-level crossover.
+/// Return `None` if no subtree swap gives a valid child.
+pub fn crossover_geno(
+    geno: &BlobGeno,
+    mate: &BlobGeno,
+    rng: &mut impl Rng,
+) -> Option<(BlobGeno, Vec<(usize, usize)>)> {
+    // compatible subtrees: both parents have a block at this position
+    let mut candidates: Vec<usize> = (1..geno.vec_tree.nodes.len())
+        .filter(|&i| is_child(geno, i) && is_child(mate, i))
+        .collect();
+    candidates.shuffle(rng);
+
+    for idx in candidates {
+        let child = swap_subtree(geno, mate, idx);
+        if !child.is_valid() {
+            continue;
+        }
+
+        let swapped = mate.vec_tree.subtree_indices(idx);
+        let pairs = (0..child.vec_tree.nodes.len())
+            .filter(|i| !swapped.contains(i))
+            .filter_map(|i| match (node_nn_id(&child, i), node_nn_id(mate, i)) {
+                (Some(child_id), Some(mate_id)) => Some((child_id, mate_id)),
+                _ => None,
+            })
+            .collect();
+        return Some((child, pairs));
+    }
+    None
+}
+
+/// uniform crossover between two NNs of the same type,
+/// each weight and bias has half chance to be taken from `mate`.
+///
+/// layers with different shapes are not changed
+pub fn crossover_nn(nn: &mut GenericNN, mate: &GenericNN, rng: &mut impl Rng) {
+    match (nn, mate) {
+        (GenericNN::BLOCKNN(nn), GenericNN::BLOCKNN(mate)) => {
+            crossover_any_nn(&mut nn.inward_nn.nn, &mate.inward_nn.nn, rng);
+            crossover_any_nn(&mut nn.outward_nn.nn, &mate.outward_nn.nn, rng);
+            if let (Some(_), Some(mate_oscillator)) = (&nn.oscillator, &mate.oscillator) {
+                if rng.gen_bool(0.5) {
+                    nn.oscillator = Some(mate_oscillator.clone());
+                }
+            }
+        }
+        (GenericNN::BRAINNN(nn), GenericNN::BRAINNN(mate)) => {
+            crossover_any_nn(&mut nn.nn, &mate.nn, rng);
+        }
+        _ => {}
+    }
+}
+
+fn crossover_any_nn(nn: &mut AnyNN, mate: &AnyNN, rng: &mut impl Rng) {
+    match (nn, mate) {
+        (AnyNN::Base(nn), AnyNN::Base(mate)) => crossover_base_nn(nn, mate, rng),
+        (AnyNN::Neat(nn), AnyNN::Neat(mate)) => crossover_neat_nn(nn, mate, rng),
+        _ => {}
+    }
+}
+
+/// genes are aligned by innovation number and node id,
+/// matching genes take their parameters from either parent.
+///
+/// Disjoint and excess genes all come from `nn`, so the topology of `nn` is kept
+fn crossover_neat_nn(nn: &mut NeatNN, mate: &NeatNN, rng: &mut impl Rng) {
+    let mate_connections: HashMap<usize, _> =
+        mate.connections.iter().map(|c| (c.innovation, c)).collect();
+    for conn in nn.connections.iter_mut() {
+        if let Some(mate_conn) = mate_connections.get(&conn.innovation) {
+            // innovations of files from different runs may disagree
+            if mate_conn.from == conn.from && mate_conn.to == conn.to && rng.gen_bool(0.5) {
+                conn.weight = mate_conn.weight;
+            }
+        }
+    }
+
+    let mate_nodes: HashMap<usize, _> = mate.nodes.iter().map(|n| (n.id, n)).collect();
+    for node in nn.nodes.iter_mut() {
+        if let Some(mate_node) = mate_nodes.get(&node.id) {
+            if mate_node.kind == node.kind && rng.gen_bool(0.5) {
+                node.bias = mate_node.bias;
+                node.activation = mate_node.activation.clone();
+            }
+        }
+    }
+}
+
+fn crossover_base_nn(nn: &mut BaseNN, mate: &BaseNN, rng: &mut impl Rng) {
+    for (layer, mate_layer) in nn.layers.iter_mut().zip(mate.layers.iter()) {
+        if layer.weights.shape() != mate_layer.weights.shape()
+            || layer.bias.shape() != mate_layer.bias.shape()
+        {
+            continue;
+        }
+        Zip::from(&mut layer.weights)
+            .and(&mate_layer.weights)
+            .for_each(|w, &m| {
+                if rng.gen_bool(0.5) {
+                    *w = m
+                }
+            });
+        Zip::from(&mut layer.bias)
+            .and(&mate_layer.bias)
+            .for_each(|b, &m| {
+                if rng.gen_bool(0.5) {
+                    *b = m
+                }
+            });
+        if let (Some(recurrent), Some(mate_recurrent)) =
+            (&mut layer.recurrent, &mate_layer.recurrent)
+        {
+            Zip::from(recurrent).and(mate_recurrent).for_each(|w, &m| {
+                if rng.gen_bool(0.5) {
+                    *w = m
+                }
+            });
+        }
+        if rng.gen_bool(0.5) {
+            layer.activation = mate_layer.activation.clone();
+        }
+    }
+}
+
+/// replace the subtree at `idx` of `geno` with the one from `mate`
+fn swap_subtree(geno: &BlobGeno, mate: &BlobGeno, idx: usize) -> BlobGeno {
+    let mut child = geno.clone();
+    child.vec_tree.clean_subtree(idx);
+
+    // where the subtree root should be, base on the parent block in `geno`
+    let parent_idx = geno.vec_tree.parent(idx).unwrap();
+    let offset = match (
+        &geno.vec_tree.nodes[parent_idx],
+        &mate.vec_tree.nodes[idx],
+    ) {
+        (Some(GenericGenoNode::Child(parent)), Some(GenericGenoNode::Child(root))) => {
+            let (pc, ps, s) = (parent.center, parent.size, root.size);
+            let center = match (idx - 1) % 4 {
+                0 => [pc[0], pc[1] + ps[1] + s[1]],
+                1 => [pc[0], pc[1] - ps[1] - s[1]],
+                2 => [pc[0] - ps[0] - s[0], pc[1]],
+                _ => [pc[0] + ps[0] + s[0], pc[1]],
+            };
+            [center[0] - root.center[0], center[1] - root.center[1]]
+        }
+        // candidates are always blocks in both genos
+        _ => panic!(),
+    };
+
+    for i in mate.vec_tree.subtree_indices(idx) {
+        let mut node = mate.vec_tree.nodes[i].clone();
+        if let Some(GenericGenoNode::Child(node)) = &mut node {
+            node.center[0] += offset[0];
+            node.center[1] += offset[1];
+        }
+        child.vec_tree.nodes[i] = node;
+    }
+    child
+}
+
+fn is_child(geno: &BlobGeno, idx: usize) -> bool {
+    matches!(geno.vec_tree.nodes[idx], Some(GenericGenoNode::Child(_)))
+}
+
+fn node_nn_id(geno: &BlobGeno, idx: usize) -> Option<usize> {
+    match &geno.vec_tree.nodes[idx] {
+        Some(GenericGenoNode::Child(node)) => node.nn_id,
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod crossover_test {
+    use rand::rngs::StdRng;
+
+    use super::*;
+    use crate::brain::{neat::Innovations, neuron::BrainNN};
+
+    /// nn_id of the mate start here, so that blocks of both parents can be told apart
+    const MATE_ID: usize = 1000;
+
+    /// give every block a distinct nn_id starting from `first`
+    fn number_nn_ids(geno: &mut BlobGeno, first: usize) {
+        for (i, nn_id) in geno.all_nn_ids_mut().into_iter().enumerate() {
+            *nn_id = Some(first + i);
+        }
+    }
+
+    #[test]
+    fn test_crossover_child_valid() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let geno = BlobGeno::new_rand(&mut rng);
+            let mate = BlobGeno::new_rand(&mut rng);
+            if let Some((child, _)) = crossover_geno(&geno, &mate, &mut rng) {
+                assert!(child.is_valid());
+            }
+        }
+    }
+
+    #[test]
+    fn test_crossover_takes_mate_subtree() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut crossed = 0;
+        for _ in 0..100 {
+            let mut geno = BlobGeno::new_rand(&mut rng);
+            let mut mate = BlobGeno::new_rand(&mut rng);
+            number_nn_ids(&mut geno, 0);
+            number_nn_ids(&mut mate, MATE_ID);
+            let Some((child, pairs)) = crossover_geno(&geno, &mate, &mut rng) else {
+                continue;
+            };
+            crossed += 1;
+
+            // blocks of the mate form exactly the subtree at the swapped position
+            let len = child.vec_tree.nodes.len();
+            let root = (0..len)
+                .find(|&i| node_nn_id(&child, i).is_some_and(|id| id >= MATE_ID))
+                .unwrap();
+            assert!(root > 0);
+            let swapped = mate.vec_tree.subtree_indices(root);
+            let removed = geno.vec_tree.subtree_indices(root);
+            for i in 0..len {
+                if swapped.contains(&i) {
+                    assert_eq!(node_nn_id(&child, i), node_nn_id(&mate, i));
+                } else if removed.contains(&i) {
+                    assert!(child.vec_tree.nodes[i].is_none());
+                } else {
+                    assert_eq!(node_nn_id(&child, i), node_nn_id(&geno, i));
+                }
+            }
+
+            // pairs are the blocks of `geno` kept in the child, with the mate block beside them
+            let shared: Vec<(usize, usize)> = (0..len)
+                .filter(|i| !swapped.contains(i))
+                .filter_map(|i| Some((node_nn_id(&child, i)?, node_nn_id(&mate, i)?)))
+                .collect();
+            assert_eq!(pairs, shared);
+            assert!(pairs.iter().all(|&(c, m)| c < MATE_ID && m >= MATE_ID));
+        }
+        assert!(crossed > 0);
+    }
+
+    #[test]
+    fn test_crossover_nn_mixes_weights() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut innovations = Innovations::default();
+        let mut nn = GenericNN::BRAINNN(BrainNN::new_rand(&mut innovations, &mut rng));
+        let mate = GenericNN::BRAINNN(BrainNN::new_rand(&mut innovations, &mut rng));
+        let original = nn.clone();
+        crossover_nn(&mut nn, &mate, &mut rng);
+
+        let weights = |nn: &GenericNN| -> Vec<f32> {
+            let GenericNN::BRAINNN(BrainNN {
+                nn: AnyNN::Base(nn),
+            }) = nn
+            else {
+                panic!("brain should be a base NN");
+            };
+            nn.layers
+                .iter()
+                .flat_map(|l| l.weights.iter().chain(l.bias.iter()).copied())
+                .collect()
+        };
+        let (child, original, mate) = (weights(&nn), weights(&original), weights(&mate));
+        let from_original = child.iter().zip(original.iter()).filter(|(c, o)| c == o).count();
+        let from_mate = child.iter().zip(mate.iter()).filter(|(c, m)| c == m).count();
+        assert_eq!(from_original + from_mate, child.len());
+        assert!(from_original > 0 && from_mate > 0);
+    }
+}
@@ -5,39 +5,42 @@ use std::f32::consts::PI;
 use rand::prelude::*;
 
 use crate::{
-    blob::geno_blob_builder::{BlobGeno, GenericGenoNode, GenoNode},
-    consts::{*, mutate_consts::*},
+    blob::{
+        geno_blob_builder::{BlobGeno, GenericGenoNode, GenoNode},
+        lineage::MutationEvent,
+    },
+    config::MutateConfig,
+    consts::*,
 };
 
-const CLAMP: [f32;2] = MUTATE_SINGLE_BLOCK_SIZE_CLAMP_SCALER;
-
 /// loop over all blobs to mutate geno.
-/// mutate tree-structure, block-size, joint-limit in the order
+/// mutate tree-structure, block-size, joint-limit in the order,
+/// applied mutations are recorded in `BlobGeno::lineage`
 /// 
 /// After the mutation, the genos and the NN is unmatched, 
 /// will be rematched in function `sync_mutate`
 pub fn mutate_geno(
-    geno_q: &mut Vec<BlobGeno>
+    geno_q: &mut [BlobGeno],
+    config: &MutateConfig,
+    rng: &mut impl Rng,
 ) {
     for mut geno in geno_q {
-        mutate_tree_structure(&mut geno);
-        mutate_block_size(&mut geno);
-        mutate_joint_limit(&mut geno)
+        mutate_tree_structure(&mut geno, config, rng);
+        mutate_block_size(&mut geno, config, rng);
+        mutate_joint_limit(&mut geno, config, rng)
     }
 }
 
 /// gain or lose limbs for a blob
 /// 
 /// gain limb might cause self confilt.
-/// set `MUTATE_GAIN_LIMB_MAX_TRY` to try if gain limb process is unsuccessful.
-pub fn mutate_tree_structure(geno: &mut BlobGeno) {
-    let mut rng: ThreadRng = thread_rng();
-
-    if !rng.gen_bool(MUTATE_TREE_STRUCTURE_PROB as f64) {
+/// set `gain_limb_max_try` to try if gain limb process is unsuccessful.
+pub fn mutate_tree_structure(geno: &mut BlobGeno, config: &MutateConfig, rng: &mut impl Rng) {
+    if !rng.gen_bool(config.tree_structure_prob as f64) {
         return;
     }
 
-    if rng.gen_bool(MUTATE_GAIN_LIMB_PROB as f64) {
+    if rng.gen_bool(config.gain_limb_prob as f64) {
         // gain limb
         let mut candidates = geno.vec_tree.branch_nodes();
         if candidates.is_empty() {
@@ -45,10 +48,10 @@ pub fn mutate_tree_structure(geno: &mut BlobGeno) {
             candidates.push(0);
         }
 
-        for _ in 0..MUTATE_GAIN_LIMB_MAX_TRY {
-            if let Some(idx) = candidates.iter().choose(&mut rand::thread_rng()) {
+        for _ in 0..config.gain_limb_max_try {
+            if let Some(idx) = candidates.iter().choose(rng) {
                 // loop till get validate limb
-                if gain_limb(geno, *idx) {
+                if gain_limb(geno, *idx, rng) {
                     break;
                 }
             }
@@ -64,7 +67,7 @@ pub fn mutate_tree_structure(geno: &mut BlobGeno) {
             // or the root only have one limb left
             return;
         }
-        if let Some(idx) = candidates.iter().choose(&mut rand::thread_rng()) {
+        if let Some(idx) = candidates.iter().choose(rng) {
             lose_limb(geno, *idx);
         }
     }
@@ -72,7 +75,7 @@ pub fn mutate_tree_structure(geno: &mut BlobGeno) {
 
 /// gain a new limb as the child of the index node
 /// return type means success or fail
-fn gain_limb(geno: &mut BlobGeno, idx: usize) -> bool {
+fn gain_limb(geno: &mut BlobGeno, idx: usize, rng: &mut impl Rng) -> bool {
     // direction and index of node
     // slots are nodes has `none` as value
     let slots: Vec<(usize, usize)> = geno
@@ -87,11 +90,12 @@ fn gain_limb(geno: &mut BlobGeno, idx: usize) -> bool {
     if slots.is_empty() {
         return false;
     }
-    let choosen = *slots.iter().choose(&mut rand::thread_rng()).unwrap();
+    let choosen = *slots.iter().choose(rng).unwrap();
     if let Some(Some(GenericGenoNode::Child(parent))) = geno.vec_tree.nodes.get(idx) {
         // TODO: new nodes should also have parent indicator
-        geno.vec_tree.nodes[choosen.1] = Some(new_rand_node(parent, choosen.0));
+        geno.vec_tree.nodes[choosen.1] = Some(new_rand_node(parent, choosen.0, rng));
         if geno.is_valid() {
+            geno.lineage.mutations.push(MutationEvent::GainLimb { index: choosen.1 });
             return true;
         } else {
             geno.vec_tree.nodes[choosen.1] = None;
@@ -108,9 +112,7 @@ fn gain_limb(geno: &mut BlobGeno, idx: usize) -> bool {
 /// 
 /// Need to know the direction of the node to generate to prevent self confilt
 /// and to calculate the presice position of the new block.
-fn new_rand_node(parent: &GenoNode, direction: usize) -> GenericGenoNode {
-    let mut rng = thread_rng();
-
+fn new_rand_node(parent: &GenoNode, direction: usize, rng: &mut impl Rng) -> GenericGenoNode {
     let parent_size = parent.size;
     let parent_center = parent.center;
 
@@ -178,6 +180,7 @@ fn new_rand_node(parent: &GenoNode, direction: usize) -> GenericGenoNode {
 fn lose_limb(geno: &mut BlobGeno, idx: usize) {
     geno.vec_tree.clean_subtree(idx);
     // geno.vec_tree.nodes[idx] = None;
+    geno.lineage.mutations.push(MutationEvent::LoseLimb { index: idx });
 }
 
 /// mutate size of blocks for a blob
@@ -185,24 +188,24 @@ fn lose_limb(geno: &mut BlobGeno, idx: usize) {
 /// all blocks of the blob can be mutate (but not must be mutate)
 /// 
 /// the mutation must valid, which means this function won't cause self confilt
-pub fn mutate_block_size(geno: &mut BlobGeno) {
-    let mut rng = thread_rng();
-
-    if !rng.gen_bool(MUTATE_BLOCK_SIZE_PROB as f64) {
+pub fn mutate_block_size(geno: &mut BlobGeno, config: &MutateConfig, rng: &mut impl Rng) {
+    if !rng.gen_bool(config.block_size_prob as f64) {
         return;
     }
 
+    let clamp = config.single_block_size_clamp_scaler;
+
     let mut potential_mutations: Vec<(usize, [f32; 2])> = Vec::new();
 
     for (index, i) in geno.vec_tree.nodes.iter().enumerate() {
         if let Some(GenericGenoNode::Child(node)) = i {
-            if !rng.gen_bool(MUTATE_SINGLE_BLOCK_SIZE_PROB as f64) {
+            if !rng.gen_bool(config.single_block_size_prob as f64) {
                 continue;
             }
             let mutation_factor_0 = rng.gen_range(0.9..=1.1);
             let mutation_factor_1 = rng.gen_range(0.9..=1.1);
-            let new_size_0 = (node.size[0] * mutation_factor_0).clamp(DEFAULT_BLOCK_SIZE[0]*CLAMP[0], DEFAULT_BLOCK_SIZE[0]*CLAMP[1]);
-            let new_size_1 = (node.size[1] * mutation_factor_1).clamp(DEFAULT_BLOCK_SIZE[1]*CLAMP[0], DEFAULT_BLOCK_SIZE[1]*CLAMP[1]);
+            let new_size_0 = (node.size[0] * mutation_factor_0).clamp(DEFAULT_BLOCK_SIZE[0]*clamp[0], DEFAULT_BLOCK_SIZE[0]*clamp[1]);
+            let new_size_1 = (node.size[1] * mutation_factor_1).clamp(DEFAULT_BLOCK_SIZE[1]*clamp[0], DEFAULT_BLOCK_SIZE[1]*clamp[1]);
     
             // Store the mutation
             potential_mutations.push((index, [new_size_0, new_size_1]));
@@ -248,6 +251,8 @@ fn mutate_single_block_size(
     // validation check
     if !geno.is_valid() {
         *geno = temp_geno;
+    } else {
+        geno.lineage.mutations.push(MutationEvent::BlockSize { index });
     }
 }
 
@@ -297,19 +302,18 @@ fn get_movevec(
 }
 
 /// Mutate joint limit of limbs
-pub fn mutate_joint_limit(geno: &mut BlobGeno){
-    let mut rng: ThreadRng = thread_rng();
-
-    for i in geno.vec_tree.nodes.iter_mut(){
-        if !rng.gen_bool(MUTATE_JOINT_LIMIT_PROB as f64) {
+pub fn mutate_joint_limit(geno: &mut BlobGeno, config: &MutateConfig, rng: &mut impl Rng){
+    for (index, i) in geno.vec_tree.nodes.iter_mut().enumerate(){
+        if !rng.gen_bool(config.joint_limit_prob as f64) {
             continue;
         }
         if let Some(GenericGenoNode::Child(node)) = i {
             let mutation_factor_0 = rng.gen_range(0.9..=1.1);
             let mutation_factor_1 = rng.gen_range(0.9..=1.1);
-            let new_limit_0 = (node.joint_limits[0] * mutation_factor_0).clamp(MUTATE_JOINT_LIMIT_MIN, 0.0);
-            let new_limit_1 = (node.joint_limits[1] * mutation_factor_1).clamp(0.0, MUTATE_JOINT_LIMIT_MAX);
+            let new_limit_0 = (node.joint_limits[0] * mutation_factor_0).clamp(config.joint_limit_min, 0.0);
+            let new_limit_1 = (node.joint_limits[1] * mutation_factor_1).clamp(0.0, config.joint_limit_max);
             node.joint_limits = [new_limit_0,new_limit_1];
+            geno.lineage.mutations.push(MutationEvent::JointLimit { index });
         }
     }
 }
\ No newline at end of file
@@ -1,5 +1,6 @@
 //! all implementations relate to mutation
 
 pub mod mutate;
+pub mod crossover;
 mod geno_mutate;
 mod nn_mutate;
\ No newline at end of file
@@ -2,20 +2,23 @@
 
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::ImpulseJoint;
+use rand::Rng;
 
 use crate::{
     blob::{
         blob::{Blob, BlobInfo},
-        geno_blob_builder::{BlobGeno, GenoBlobBuilder},
+        geno_blob_builder::{BlobGeno, GenericGenoNode, GenoBlobBuilder},
+        lineage::MutationEvent,
     },
     brain::{
+        neat::Innovations,
         neuron::{BlockNN, GenericNN},
         resource::BevyBlockNeurons,
     },
-    componet::ColliderFlag,
-    consts::MUTATE_AND_REFRESH_KEYCODE,
+    componet::BlobColliderFilter,
+    config::SimConfig,
     contorl::{resource::TrainMutPipe, update::block_action},
-    physics::world::Wall,
+    rng::SimRng,
 };
 
 use super::{geno_mutate::mutate_geno, nn_mutate::mutate_nn};
@@ -49,10 +52,14 @@ pub fn mutate_and_refresh(
     mut bbn: ResMut<BevyBlockNeurons>,
     geno_info_q: Query<(&BlobGeno, &BlobInfo)>,
     blob_q: Query<Entity, With<Blob>>,
-    collider_q: Query<Entity, (With<ColliderFlag>, Without<Wall>)>,
+    collider_q: Query<Entity, BlobColliderFilter>,
     joint_q: Query<Entity, With<ImpulseJoint>>,
     input: Res<Input<KeyCode>>,
+    config: Res<SimConfig>,
+    mut innovations: ResMut<Innovations>,
+    mut rng: ResMut<SimRng>,
 ) {
+    let rng = &mut *rng;
     let mut geno_vec = Vec::<BlobGeno>::new();
     let mut info_vec = Vec::<&BlobInfo>::new();
     for (geno, info) in geno_info_q.iter() {
@@ -60,11 +67,19 @@ pub fn mutate_and_refresh(
         info_vec.push(info);
     }
 
-    if input.just_pressed(MUTATE_AND_REFRESH_KEYCODE) {
-        mutate_geno(&mut geno_vec);
-        mutate_nn(&mut bbn.nnvec);
+    if input.just_pressed(config.keys.mutate_and_refresh) {
+        mutate_geno(&mut geno_vec, config.mutate(), &mut rng.mutate);
+        let mutated = mutate_nn(
+            &mut bbn.nnvec,
+            &[],
+            config.mutate(),
+            &mut innovations,
+            &mut rng.mutate,
+        );
+        record_nn_mutation(&mut geno_vec, &mutated);
 
-        let (mut genovec, nnvec) = sync_mutate(&mut geno_vec, &mut bbn);
+        let (mut genovec, nnvec) =
+            sync_mutate(&mut geno_vec, &mut bbn, &mut innovations, &mut rng.nn);
 
         // despawn
         for entity in blob_q.iter().chain(collider_q.iter()).chain(joint_q.iter()) {
@@ -73,7 +88,12 @@ pub fn mutate_and_refresh(
 
         // temp empty vector for builder
         let mut temp_nnvec = Vec::<GenericNN>::new();
-        let mut builder = GenoBlobBuilder::from_commands(commands, &mut temp_nnvec);
+        let mut builder = GenoBlobBuilder::from_commands(
+            commands,
+            &mut temp_nnvec,
+            &mut innovations,
+            &mut rng.nn,
+        );
 
         for (geno, &info) in genovec.iter_mut().zip(info_vec.iter()) {
             builder.build(geno, info.center_block_pos.to_array())
@@ -93,23 +113,41 @@ pub fn mutate_and_refresh_after_train(
     mut pipe: ResMut<TrainMutPipe>,
     // geno_info_q: Query<(&BlobGeno, &BlobInfo)>,
     blob_q: Query<Entity, With<Blob>>,
-    collider_q: Query<Entity, (With<ColliderFlag>, Without<Wall>)>,
+    collider_q: Query<Entity, BlobColliderFilter>,
     joint_q: Query<Entity, With<ImpulseJoint>>,
     // input: Res<Input<KeyCode>>,
+    config: Res<SimConfig>,
+    mut innovations: ResMut<Innovations>,
+    mut rng: ResMut<SimRng>,
 ) {
     // emtpy pipe means no tournament selection preformed in this frame
     if pipe.is_empty() {
         return;
     }
 
-    let (mut pipe_genovec, infovec, mut pipe_nnvec) = pipe.pop();
+    let (mut pipe_genovec, infovec, mut pipe_nnvec, elite) = pipe.pop();
 
-    mutate_geno(&mut pipe_genovec);
-    mutate_nn(&mut pipe_nnvec);
+    // elites are kept unmutated
+    let elite_nn_ids: Vec<usize> = pipe_genovec[..elite]
+        .iter()
+        .flat_map(|geno| geno.all_usize_nn_ids())
+        .collect();
+
+    let rng = &mut *rng;
+    mutate_geno(&mut pipe_genovec[elite..], config.mutate(), &mut rng.mutate);
+    let mutated = mutate_nn(
+        &mut pipe_nnvec,
+        &elite_nn_ids,
+        config.mutate(),
+        &mut innovations,
+        &mut rng.mutate,
+    );
+    record_nn_mutation(&mut pipe_genovec, &mutated);
 
     bbn.nnvec = pipe_nnvec;
 
-    let (mut genovec, nnvec) = sync_mutate(&mut pipe_genovec, &mut bbn);
+    let (mut genovec, nnvec) =
+        sync_mutate(&mut pipe_genovec, &mut bbn, &mut innovations, &mut rng.nn);
 
     // despawn
     for entity in blob_q.iter().chain(collider_q.iter()).chain(joint_q.iter()) {
@@ -118,7 +156,8 @@ pub fn mutate_and_refresh_after_train(
 
     // temp empty vector for builder
     let mut temp_nnvec = Vec::<GenericNN>::new();
-    let mut builder = GenoBlobBuilder::from_commands(commands, &mut temp_nnvec);
+    let mut builder =
+        GenoBlobBuilder::from_commands(commands, &mut temp_nnvec, &mut innovations, &mut rng.nn);
 
     for (geno, info) in genovec.iter_mut().zip(infovec.iter()) {
         builder.build(geno, info.center_block_pos.to_array())
@@ -128,6 +167,28 @@ pub fn mutate_and_refresh_after_train(
     bbn.nnvec = nnvec;
 }
 
+/// record NN perturbation in the lineage of blobs owning the mutated NN
+///
+/// new limbs from geno mutation don't have NN yet, they are skipped
+fn record_nn_mutation(genovec: &mut [BlobGeno], mutated: &[usize]) {
+    for geno in genovec.iter_mut() {
+        let blocks = geno
+            .vec_tree
+            .nodes
+            .iter()
+            .filter(|node| match node {
+                Some(GenericGenoNode::Child(node)) => {
+                    node.nn_id.map_or(false, |id| mutated.contains(&id))
+                }
+                _ => false,
+            })
+            .count();
+        if blocks > 0 {
+            geno.lineage.mutations.push(MutationEvent::Nn { blocks });
+        }
+    }
+}
+
 /// mutated blob may gain or lose NN, sync it with resource.
 /// 
 /// If blob gain limbs, new NN will be append to the end of the NN vector in resource.
@@ -138,6 +199,8 @@ pub fn mutate_and_refresh_after_train(
 fn sync_mutate(
     geno_q: &mut Vec<BlobGeno>,
     bbn: &mut ResMut<BevyBlockNeurons>,
+    innovations: &mut Innovations,
+    rng: &mut impl Rng,
 ) -> (Vec<BlobGeno>, Vec<GenericNN>) {
     let mut existed_nn_ids = Vec::<usize>::new();
 
@@ -145,7 +208,7 @@ fn sync_mutate(
         // generate NN for new limbs
         for id in geno.all_nn_ids_mut() {
             if id.is_none() {
-                bbn.nnvec.push(GenericNN::BLOCKNN(BlockNN::default()));
+                bbn.nnvec.push(GenericNN::BLOCKNN(BlockNN::new_rand(innovations, rng)));
                 *id = Some(bbn.nnvec.len() - 1);
             }
             existed_nn_ids.push(id.clone().unwrap());
@@ -192,6 +255,11 @@ fn sync_mutate(
         }
     }
 
+    // respawned blobs start without recurrent state
+    for nn in bbn.nnvec.iter_mut() {
+        nn.reset_state();
+    }
+
     // copy geno
     (Vec::from_iter(geno_q.iter().cloned()), bbn.nnvec.clone())
 }
@@ -5,60 +5,167 @@ use rand_distr::{Distribution, Normal};
 
 use crate::{
     brain::{
-        neuron::{BlockNN, BrainNN, GenericNN},
-        nn::BaseNN,
+        neat::{Innovations, NeatNN, NodeKind},
+        neuron::{AnyNN, BlockNN, BrainNN, GenericNN},
+        nn::{Activation, BaseNN},
+        oscillator::Oscillator,
     },
-    consts::mutate_consts::*,
+    config::MutateConfig,
 };
 
 /// mutate Neuron Networks
-pub fn mutate_nn(nnvec: &mut Vec<GenericNN>) {
-    for nn in nnvec.iter_mut() {
-        let mut rng: ThreadRng = thread_rng();
+/// 
+/// NN with id in `frozen` won't be mutated,
+/// structural mutations of NEAT networks are recorded in `innovations`
+/// 
+/// return the ids of mutated NN
+pub fn mutate_nn(
+    nnvec: &mut Vec<GenericNN>,
+    frozen: &[usize],
+    config: &MutateConfig,
+    innovations: &mut Innovations,
+    rng: &mut impl Rng,
+) -> Vec<usize> {
+    let mut mutated = Vec::<usize>::new();
+    for (id, nn) in nnvec.iter_mut().enumerate() {
+        if frozen.contains(&id) {
+            continue;
+        }
 
-        if !rng.gen_bool(MUTATE_NN_PORB as f64) {
+        if !rng.gen_bool(config.nn_prob as f64) {
             continue;
         }
 
         match nn {
-            GenericNN::BRAINNN(nn) => mutate_brain_nn(nn),
-            GenericNN::BLOCKNN(nn) => mutate_block_nn(nn),
+            GenericNN::BRAINNN(nn) => mutate_brain_nn(nn, config, innovations, rng),
+            GenericNN::BLOCKNN(nn) => mutate_block_nn(nn, config, innovations, rng),
         }
+        mutated.push(id);
     }
+    mutated
 }
 
-fn mutate_block_nn(nn: &mut BlockNN) {
-    mutate_base_nn(&mut nn.inward_nn.nn);
-    mutate_base_nn(&mut nn.outward_nn.nn);
+fn mutate_block_nn(
+    nn: &mut BlockNN,
+    config: &MutateConfig,
+    innovations: &mut Innovations,
+    rng: &mut impl Rng,
+) {
+    mutate_any_nn(&mut nn.inward_nn.nn, config, innovations, rng);
+    mutate_any_nn(&mut nn.outward_nn.nn, config, innovations, rng);
+    if let Some(oscillator) = &mut nn.oscillator {
+        mutate_oscillator(oscillator, config, rng);
+    }
 }
 
-fn mutate_brain_nn(nn: &mut BrainNN) {
-    mutate_base_nn(&mut nn.nn);
+/// add an random value to each oscillator parameter, with the same chance as a weight
+fn mutate_oscillator(oscillator: &mut Oscillator, config: &MutateConfig, rng: &mut impl Rng) {
+    let normal = Normal::new(0.0, config.nn_std).unwrap();
+    for param in [
+        &mut oscillator.frequency,
+        &mut oscillator.phase,
+        &mut oscillator.amplitude,
+        &mut oscillator.coupling,
+    ] {
+        if rng.gen_bool(config.nn_weight_prob as f64) {
+            *param += normal.sample(rng) as f32;
+        }
+    }
+    oscillator.clamp();
 }
 
+fn mutate_brain_nn(
+    nn: &mut BrainNN,
+    config: &MutateConfig,
+    innovations: &mut Innovations,
+    rng: &mut impl Rng,
+) {
+    mutate_any_nn(&mut nn.nn, config, innovations, rng);
+}
 
-/// add an random value to the existed weight and bias
-fn mutate_base_nn(nn: &mut BaseNN) {
-    let normal = Normal::new(0.0, MUTATE_NN_STD).unwrap();
+fn mutate_any_nn(
+    nn: &mut AnyNN,
+    config: &MutateConfig,
+    innovations: &mut Innovations,
+    rng: &mut impl Rng,
+) {
+    match nn {
+        AnyNN::Base(nn) => mutate_base_nn(nn, config, rng),
+        AnyNN::Neat(nn) => mutate_neat_nn(nn, config, innovations, rng),
+    }
+}
+
+/// perturb weights, biases and activations like `mutate_base_nn`,
+/// then maybe add a node and a connection
+fn mutate_neat_nn(
+    nn: &mut NeatNN,
+    config: &MutateConfig,
+    innovations: &mut Innovations,
+    rng: &mut impl Rng,
+) {
+    let normal = Normal::new(0.0, config.nn_std).unwrap();
+
+    for conn in nn.connections.iter_mut() {
+        if rng.gen_bool(config.nn_weight_prob as f64) {
+            conn.weight += normal.sample(rng) as f32;
+        }
+    }
+
+    for node in nn.nodes.iter_mut() {
+        if node.kind == NodeKind::Input {
+            continue;
+        }
+        if rng.gen_bool(config.nn_bias_prob as f64) {
+            node.bias += normal.sample(rng) as f32;
+        }
+        if rng.gen_bool(config.nn_activation_prob as f64) {
+            node.activation = Activation::ALL.choose(rng).unwrap().clone();
+        }
+    }
+
+    if rng.gen_bool(config.neat_add_node_prob as f64) {
+        nn.add_node(innovations, rng);
+    }
+    if rng.gen_bool(config.neat_add_connection_prob as f64) {
+        nn.add_connection(innovations, rng);
+    }
+}
 
-    // Use the thread_rng to get a thread-local random number generator
-    let mut rng: ThreadRng = thread_rng();
+
+/// add an random value to the existed weight and bias
+fn mutate_base_nn(nn: &mut BaseNN, config: &MutateConfig, rng: &mut impl Rng) {
+    let normal = Normal::new(0.0, config.nn_std).unwrap();
 
     for layer in &mut nn.layers {
         // Mutate weights
         for weight in layer.weights.iter_mut() {
-            if !rng.gen_bool(MUTATE_NN_WEIGHT_PROB as f64) {
+            if !rng.gen_bool(config.nn_weight_prob as f64) {
                 continue;
             }
-            *weight += normal.sample(&mut rng) as f32;
+            *weight += normal.sample(rng) as f32;
         }
 
         // Mutate biases
         for bias in layer.bias.iter_mut() {
-            if !rng.gen_bool(MUTATE_NN_BIAS_PROB as f64) {
+            if !rng.gen_bool(config.nn_bias_prob as f64) {
                 continue;
             }
-            *bias += normal.sample(&mut rng) as f32;
+            *bias += normal.sample(rng) as f32;
+        }
+
+        // Mutate recurrent weights
+        if let Some(recurrent) = &mut layer.recurrent {
+            for weight in recurrent.iter_mut() {
+                if !rng.gen_bool(config.nn_weight_prob as f64) {
+                    continue;
+                }
+                *weight += normal.sample(rng) as f32;
+            }
+        }
+
+        // Mutate activation
+        if rng.gen_bool(config.nn_activation_prob as f64) {
+            layer.activation = Activation::ALL.choose(rng).cloned();
         }
     }
 }
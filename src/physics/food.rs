@@ -0,0 +1,198 @@
+//! food of the foraging task
+//!
+//! Food only exists in `TrainingMode::Forage`.
+//! It grows around a few fixed patch centers (or anywhere if there is no patch),
+//! is eaten by the first blob that touches it and grows back over time.
+
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::prelude::*;
+use rand_distr::Normal;
+
+use crate::{
+    blob::blob::BlobInfo,
+    componet::ColliderFlag,
+    config::{FoodConfig, SimConfig, TrainingMode},
+    rng::SimRng,
+};
+
+/// food flag, different from `ColliderFlag`
+#[derive(Component)]
+pub struct Food;
+
+/// centers food grows around, decided at startup
+#[derive(Resource, Default)]
+pub struct FoodPatches(pub Vec<Vec2>);
+
+pub fn is_forage(config: Res<SimConfig>) -> bool {
+    config.training.mode == TrainingMode::Forage
+}
+
+/// place patches and fill the world with food
+pub fn setup_food(mut commands: Commands, mut rng: ResMut<SimRng>, config: Res<SimConfig>) {
+    let world_size = Vec2::from(config.world_size());
+    let food = &config.food;
+    let patches = FoodPatches(
+        (0..food.patches)
+            .map(|_| rand_point(world_size * 0.5, &mut rng.food))
+            .collect(),
+    );
+    for _ in 0..target_count(&config) {
+        let pos = food_point(&patches, food, world_size, &mut rng.food);
+        spawn_food(&mut commands, pos, food);
+    }
+    commands.insert_resource(patches);
+}
+
+/// grow food back while there is less than `FoodConfig::density`
+pub fn respawn_food(
+    mut commands: Commands,
+    food_q: Query<(), With<Food>>,
+    patches: Res<FoodPatches>,
+    mut rng: ResMut<SimRng>,
+    config: Res<SimConfig>,
+) {
+    let food = &config.food;
+    if food_q.iter().count() >= target_count(&config) {
+        return;
+    }
+    let chance = (food.respawn_rate * config.physics.rapier_dt).clamp(0.0, 1.0);
+    if rng.food.gen_bool(chance as f64) {
+        let pos = food_point(
+            &patches,
+            food,
+            Vec2::from(config.world_size()),
+            &mut rng.food,
+        );
+        spawn_food(&mut commands, pos, food);
+    }
+}
+
+/// blobs eat the food their blocks touch, each food is eaten once
+pub fn eat_food(
+    mut commands: Commands,
+    mut cf_events: EventReader<ContactForceEvent>,
+    food_q: Query<(), With<Food>>,
+    parent_q: Query<&Parent>,
+    mut blob_q: Query<&mut BlobInfo>,
+    config: Res<SimConfig>,
+) {
+    let mut eaten = HashSet::<Entity>::new();
+    for event in cf_events.iter() {
+        for (food, block) in [
+            (event.collider1, event.collider2),
+            (event.collider2, event.collider1),
+        ] {
+            if !food_q.contains(food) || eaten.contains(&food) {
+                continue;
+            }
+            // block is the child of blob
+            let Ok(blob) = parent_q.get(block) else {
+                continue;
+            };
+            if let Ok(mut info) = blob_q.get_mut(blob.get()) {
+                info.eat(config.food.energy);
+                eaten.insert(food);
+                commands.entity(food).despawn();
+            }
+        }
+    }
+}
+
+fn spawn_food(commands: &mut Commands, pos: Vec2, food: &FoodConfig) {
+    commands.spawn((
+        Collider::ball(food.radius),
+        TransformBundle::from_transform(Transform::from_xyz(pos.x, pos.y, 0.0)),
+        ColliderFlag::FOOD,
+        Food,
+    ));
+}
+
+/// food count per 1000 * 1000 area
+fn target_count(config: &SimConfig) -> usize {
+    let [width, height] = config.world_size();
+    (config.food.density * width * height / 1e6) as usize
+}
+
+/// random point in `[-half, half]`
+fn rand_point(half: Vec2, rng: &mut impl Rng) -> Vec2 {
+    Vec2::new(
+        rng.gen_range(-half.x..half.x),
+        rng.gen_range(-half.y..half.y),
+    )
+}
+
+/// position of a new food, around a random patch and inside the walls
+fn food_point(
+    patches: &FoodPatches,
+    food: &FoodConfig,
+    world_size: Vec2,
+    rng: &mut impl Rng,
+) -> Vec2 {
+    let half = world_size * 0.5 - food.radius;
+    let Some(center) = patches.0.choose(rng) else {
+        return rand_point(half, rng);
+    };
+    let normal = Normal::new(0.0, food.patch_radius.max(f32::EPSILON)).unwrap();
+    let offset = Vec2::new(normal.sample(rng), normal.sample(rng));
+    (*center + offset).clamp(-half, half)
+}
+
+/// smell of food at `pos`, return the intensity and its gradient.
+///
+/// Each food smells `exp(-d^2 / (2 * range^2))` at distance `d`,
+/// the gradient is scaled by `range` so that it does not depend on the world scale
+pub fn smell(pos: Vec2, foods: impl Iterator<Item = Vec2>, range: f32) -> (f32, Vec2) {
+    let mut intensity = 0.0;
+    let mut gradient = Vec2::ZERO;
+    for food in foods {
+        let d = food - pos;
+        let w = (-d.length_squared() / (2.0 * range * range)).exp();
+        intensity += w;
+        gradient += w * d / range;
+    }
+    (intensity, gradient)
+}
+
+#[cfg(test)]
+mod food_test {
+    use super::*;
+    use crate::{
+        componet::{BlobColliderFilter, BlobEntityIndex},
+        physics::world::Wall,
+    };
+
+    #[test]
+    fn test_smell() {
+        let foods = [Vec2::new(100.0, 0.0), Vec2::new(-300.0, 0.0)];
+        let (intensity, gradient) = smell(Vec2::ZERO, foods.into_iter(), 100.0);
+        // the closer food dominates
+        assert!(gradient.x > 0.0);
+        assert_eq!(gradient.y, 0.0);
+        assert!((intensity - (-0.5f32).exp() - (-4.5f32).exp()).abs() < 1e-6);
+
+        let (intensity, gradient) = smell(Vec2::ZERO, std::iter::empty(), 100.0);
+        assert_eq!((intensity, gradient), (0.0, Vec2::ZERO));
+    }
+
+    #[test]
+    fn test_food_survives_reset() {
+        let mut world = World::new();
+        let food = world.spawn((ColliderFlag::FOOD, Food)).id();
+        world.spawn((ColliderFlag::WALL, Wall));
+        world.spawn(ColliderFlag::BLOCK(BlobEntityIndex(Some(0))));
+
+        // same despawn as a generation reset
+        let blob_colliders: Vec<Entity> = world
+            .query_filtered::<Entity, BlobColliderFilter>()
+            .iter(&world)
+            .collect();
+        assert_eq!(blob_colliders.len(), 1);
+        for entity in blob_colliders {
+            world.despawn(entity);
+        }
+        assert!(world.get_entity(food).is_some());
+    }
+}
@@ -2,4 +2,5 @@
 
 pub mod physical_world;
 pub mod world;
-pub mod rules;
\ No newline at end of file
+pub mod rules;
+pub mod food;
\ No newline at end of file
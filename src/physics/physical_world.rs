@@ -3,8 +3,10 @@
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 
+use crate::config::config;
 use crate::physics::rules::*;
 use crate::physics::world::setup_walls;
+use crate::physics::food::{eat_food, is_forage, respawn_food, setup_food};
 
 /// all implementations relate to physic and the world.
 /// 
@@ -12,7 +14,11 @@ use crate::physics::world::setup_walls;
 /// - bevy plugin
 /// - world setup
 /// - gravity setup
+/// - food of foraging task
 /// - viscosity force
+/// - time step contorl
+///
+/// debug render is skipped in headless mode
 pub struct PhysiWorldPlugin;
 
 impl Plugin for PhysiWorldPlugin {
@@ -25,11 +31,25 @@ impl Plugin for PhysiWorldPlugin {
                 // apply_forces
             ),
         )
+        .add_systems(Startup, setup_food.run_if(is_forage))
         .add_systems(Update, viscosity)
-        .add_plugins((
-            // raiper
-            RapierPhysicsPlugin::<NoUserData>::default(),
-            RapierDebugRenderPlugin::default(),
-        ));
+        .add_systems(
+            Update,
+            (eat_food, respawn_food.after(eat_food)).run_if(is_forage),
+        )
+        // raiper
+        .add_plugins(RapierPhysicsPlugin::<NoUserData>::default())
+        // using Fixed timestep so that the simulation can speed up
+        .insert_resource(RapierConfiguration {
+            timestep_mode: TimestepMode::Fixed {
+                dt: config().physics.rapier_dt,
+                substeps: config().physics.rapier_substeps,
+            },
+            ..default()
+        });
+
+        if !config().headless {
+            app.add_plugins(RapierDebugRenderPlugin::default());
+        }
     }
 }
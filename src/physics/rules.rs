@@ -5,17 +5,23 @@ use std::time::Instant;
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 
-use crate::consts::*;
+use crate::{
+    config::{SimConfig, TrainingMode},
+    consts::*,
+};
 
-pub fn setup_gravity(mut rapier_config: ResMut<RapierConfiguration>) {
-    if TRAINING_MODE == "swim" {
+pub fn setup_gravity(mut rapier_config: ResMut<RapierConfiguration>, config: Res<SimConfig>) {
+    if config.training.mode != TrainingMode::Walk {
         rapier_config.gravity = Vec2::ZERO;
     }
 }
 
 /// Create drag force for under-water simulation
 /// Cost about 5% of total running time in Physical Simulation
-pub fn viscosity(mut block_q: Query<(&Collider, &Transform, &Velocity, &mut ExternalForce)>) {
+pub fn viscosity(
+    mut block_q: Query<(&Collider, &Transform, &Velocity, &mut ExternalForce)>,
+    config: Res<SimConfig>,
+) {
     let start_time = Instant::now();
     // // parallel implementation, save about 3% of running time (in physical simulation)
     // block_q
@@ -59,7 +65,7 @@ pub fn viscosity(mut block_q: Query<(&Collider, &Transform, &Velocity, &mut Exte
     }
 
     let duration = Instant::now() - start_time;
-    if PRINT_FUNCTION_TIME && duration >= MIN_PRINT_DURATION{
+    if config.debug.should_print(duration) {
         println!("viscosity: {:?}", duration);
     }
 }
@@ -3,21 +3,24 @@
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 
-use crate::{consts::*, componet::ColliderFlag};
+use crate::{
+    componet::ColliderFlag,
+    config::{SimConfig, TrainingMode},
+};
 
 /// wall flag, different from `ColliderFlag`
 #[derive(Component)]
 pub struct Wall;
 
-pub fn setup_walls(mut commands: Commands) {
+/// the bottom wall in walk mode, blobs walk on it
+#[derive(Component)]
+pub struct Ground;
 
-    let mut half_window_width = WORLD_WIDTH_SWIM / 2.0;
-    let mut half_window_height = WORLD_HEIGHT_SWIM /2.0;
-    
-    if TRAINING_MODE == "walk" {
-        half_window_width = WORLD_WIDTH_WALK / 2.0;
-        half_window_height = WORLD_HEIGHT_WALK /2.0;
-    }
+pub fn setup_walls(mut commands: Commands, config: Res<SimConfig>) {
+
+    let [world_width, world_height] = config.world_size();
+    let half_window_width = world_width / 2.0;
+    let half_window_height = world_height / 2.0;
 
 
     // Left wall
@@ -45,10 +48,13 @@ pub fn setup_walls(mut commands: Commands) {
     ));
 
     // Bottom wall
-    commands.spawn((
+    let mut bottom = commands.spawn((
         Collider::cuboid(half_window_width, 1.0),
         TransformBundle::from_transform(Transform::from_xyz(0.0, -half_window_height, 0.0)),
         ColliderFlag::WALL,
         Wall
     ));
+    if config.training.mode == TrainingMode::Walk {
+        bottom.insert(Ground);
+    }
 }
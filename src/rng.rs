@@ -0,0 +1,61 @@
+//! seeded random number generators, so that a run can be reproduced
+//!
+//! every random decision of the simulation draws from `SimRng`,
+//! two runs with the same seed and config produce the same genomes and fitness logs.
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::config::SimConfig;
+
+/// Bevy resource holding all random number generators of the simulation.
+///
+/// Every subsystem draws from its own stream derived from the seed,
+/// so that drawing more numbers in one subsystem
+/// (e.g. a larger NN) won't shift the numbers of the others.
+#[derive(Resource, Debug, Clone)]
+pub struct SimRng {
+    seed: u64,
+    /// random genotypes and new limbs
+    pub geno: StdRng,
+    /// NN initialization and random outputs
+    pub nn: StdRng,
+    /// mutation of geno and NN
+    pub mutate: StdRng,
+    /// selection and reproduction in training
+    pub train: StdRng,
+    /// spawn positions
+    pub spawn: StdRng,
+    /// food patches and positions
+    pub food: StdRng,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            geno: stream(seed, 0),
+            nn: stream(seed, 1),
+            mutate: stream(seed, 2),
+            train: stream(seed, 3),
+            spawn: stream(seed, 4),
+            food: stream(seed, 5),
+        }
+    }
+
+    /// use the seed in config, or a random seed if it is not set
+    pub fn from_config(config: &SimConfig) -> Self {
+        Self::new(config.training.seed.unwrap_or_else(rand::random))
+    }
+
+    /// the seed of all streams
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+/// derive an independent stream from the seed
+fn stream(seed: u64, id: u64) -> StdRng {
+    // golden ratio constant spreads the stream ids over the seed space
+    StdRng::seed_from_u64(seed ^ id.wrapping_mul(0x9E37_79B9_7F4A_7C15))
+}